@@ -1,32 +1,19 @@
+use aoc2019::computer::topology::Topology;
 use aoc2019::computer::*;
 use aoc2019::*;
 
-fn run_q1(prog: &Program, settings: &[Int]) -> Result<Int> {
-    let mut val = 0;
-    for &setting in settings {
-        let input = [setting, val];
-        val = prog.start_with_input(&input).execute()?[0];
+fn run(prog: &Program, settings: &[Int], feedback: bool) -> Result<Int> {
+    let mut topology = Topology::new();
+    let amplifiers = topology.add_copies(prog, settings.len(), settings);
+    topology.seed(amplifiers[0], &[0]);
+    if feedback {
+        topology.ring(&amplifiers);
+    } else {
+        topology.chain(&amplifiers);
     }
-    Ok(val)
-}
-
-fn run_q2(prog: &Program, settings: &[Int]) -> Result<Int> {
-    let mut val = 0;
-    let mut instants: Vec<Instant> = Vec::with_capacity(5);
-    for &setting in settings {
-        instants.push(prog.start_with_input(&[setting]));
-    }
-    for i in (0..settings.len()).cycle() {
-        let instant = &mut instants[i];
-        instant.push_input(val);
-        instant.step()?;
-        if let Some(out) = instant.pop_output() {
-            val = out;
-        } else {
-            break;
-        }
-    }
-    Ok(val)
+    let outputs = topology.run()?;
+    let last = amplifiers.last().context("no amplifiers")?;
+    outputs[*last].last().copied().context("no output")
 }
 
 fn main() -> Result<()> {
@@ -34,14 +21,14 @@ fn main() -> Result<()> {
 
     let ans1 = (0..5)
         .permutations(5)
-        .filter_map(|settings| run_q1(&prog, &settings).ok())
+        .filter_map(|settings| run(&prog, &settings, false).ok())
         .max()
         .unwrap();
     println!("ans1={:?}", ans1);
 
     let ans2 = (5..10)
         .permutations(5)
-        .filter_map(|settings| run_q2(&prog, &settings).ok())
+        .filter_map(|settings| run(&prog, &settings, true).ok())
         .max()
         .unwrap();
     println!("ans2={:?}", ans2);
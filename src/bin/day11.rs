@@ -1,5 +1,6 @@
 use aoc2019::computer::*;
 use aoc2019::*;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 
@@ -42,19 +43,20 @@ impl Into<Int> for Color {
 struct Point(i32, i32);
 
 struct Robot {
-    instant: Instant,
     direction: Direction,
     loc: Point,
     paints: HashMap<Point, Color>,
+    /// Whether the next output is a turn rather than a color.
+    turning: bool,
 }
 
 impl Robot {
-    fn new(prog: &Program) -> Self {
+    fn new() -> Self {
         Self {
-            instant: prog.start(),
             direction: Direction::N,
             loc: Point(0, 0),
             paints: HashMap::new(),
+            turning: false,
         }
     }
 
@@ -62,51 +64,58 @@ impl Robot {
         *self.paints.get(&loc).unwrap_or(&Color::Black)
     }
 
-    fn push_input(&mut self, val: Int) {
-        self.instant.push_input(val);
+    fn sense(&self) -> Int {
+        self.get_color(self.loc).into()
     }
 
-    fn pop_output(&mut self) -> Result<Int> {
-        self.instant.pop_output().context("failed to get output")
+    fn act(&mut self, val: Int) -> Result<()> {
+        self.turning = !self.turning;
+        if self.turning {
+            self.paints.insert(self.loc, Color::try_from(val)?);
+            return Ok(());
+        }
+        self.direction = match val {
+            0 => match self.direction {
+                Direction::N => Direction::W,
+                Direction::E => Direction::N,
+                Direction::S => Direction::E,
+                Direction::W => Direction::S,
+            },
+            1 => match self.direction {
+                Direction::N => Direction::E,
+                Direction::E => Direction::S,
+                Direction::S => Direction::W,
+                Direction::W => Direction::N,
+            },
+            _ => bail!("invalid turn"),
+        };
+        match self.direction {
+            Direction::N => self.loc.1 -= 1,
+            Direction::E => self.loc.0 += 1,
+            Direction::S => self.loc.1 += 1,
+            Direction::W => self.loc.0 -= 1,
+        }
+        Ok(())
     }
 
-    fn run(&mut self, color: Color) -> Result<()> {
-        self.push_input(color.into());
-        loop {
-            if self.instant.step()? == StepResult::Halt {
-                break;
-            }
-            let color = Color::try_from(self.pop_output()?)?;
-            self.paints.insert(self.loc, color);
-            if self.instant.step()? == StepResult::Halt {
-                break;
-            }
-            let turn = self.pop_output()?;
-            self.direction = match turn {
-                0 => match self.direction {
-                    Direction::N => Direction::W,
-                    Direction::E => Direction::N,
-                    Direction::S => Direction::E,
-                    Direction::W => Direction::S,
-                },
-                1 => match self.direction {
-                    Direction::N => Direction::E,
-                    Direction::E => Direction::S,
-                    Direction::S => Direction::W,
-                    Direction::W => Direction::N,
-                },
-                _ => bail!("invalid turn"),
-            };
-            match self.direction {
-                Direction::N => self.loc.1 -= 1,
-                Direction::E => self.loc.0 += 1,
-                Direction::S => self.loc.1 += 1,
-                Direction::W => self.loc.0 -= 1,
-            }
-            self.push_input(self.get_color(self.loc).into());
+    fn run(prog: &Program, color: Color) -> Result<Self> {
+        let mut robot = Robot::new();
+        // the first panel gets painted anyway, so this does not change the count
+        robot.paints.insert(robot.loc, color);
+        let robot = RefCell::new(robot);
+        let mut error = None;
+        let result = prog.start().run_with(
+            || robot.borrow().sense(),
+            Actions {
+                robot: &robot,
+                error: &mut error,
+            },
+        );
+        if let Some(e) = error {
+            return Err(e);
         }
-
-        Ok(())
+        result?;
+        Ok(robot.into_inner())
     }
 
     fn paint_region(&self) -> (i32, i32, i32, i32) {
@@ -125,15 +134,31 @@ impl Robot {
     }
 }
 
+/// Passes outputs to the robot, stopping the program on an invalid one.
+struct Actions<'a> {
+    robot: &'a RefCell<Robot>,
+    error: &'a mut Option<Error>,
+}
+
+impl OutputSink for Actions<'_> {
+    fn send_output(&mut self, val: Int) -> bool {
+        match self.robot.borrow_mut().act(val) {
+            Ok(()) => true,
+            Err(e) => {
+                *self.error = Some(e);
+                false
+            }
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let prog = Program::load_from_input("day11.txt")?;
 
-    let mut robot = Robot::new(&prog);
-    robot.run(Color::Black)?;
+    let robot = Robot::run(&prog, Color::Black)?;
     println!("ans1={:?}", robot.paints.len());
 
-    let mut robot = Robot::new(&prog);
-    robot.run(Color::White)?;
+    let robot = Robot::run(&prog, Color::White)?;
     let region = robot.paint_region();
     for y in region.2..=region.3 {
         for x in region.0..=region.1 {
@@ -42,7 +42,7 @@ impl Game {
     fn new(prog: &Program, free_to_play: bool) -> Self {
         let mut instant = prog.start();
         if free_to_play {
-            instant.mem[0] = 2;
+            instant.mem.write(0, 2);
         }
         Self {
             instant,
@@ -53,13 +53,7 @@ impl Game {
 
     fn step(&mut self) -> Result<bool> {
         self.tiles.clear();
-        let is_halt = loop {
-            match self.instant.step()? {
-                StepResult::Halt => break true,
-                StepResult::WaitInput => break false,
-                StepResult::Output => continue,
-            }
-        };
+        let is_halt = self.instant.run_until_input()? == RunOutcome::Halted;
         for mut chunk in &self.instant.output_iter().chunks(3) {
             let x = *chunk.next().context("failed to read output")?;
             let y = *chunk.next().context("failed to read output")?;
@@ -64,14 +64,14 @@ fn q1(prog: &Program) -> Result<SearchState> {
             next_state.loc = next_loc;
             next_state.depth += 1;
             next_state.inst.push_input(input.into());
-            match next_state.inst.step()? {
-                StepResult::Halt | StepResult::WaitInput => continue,
-                StepResult::Output => match next_state.inst.pop_output().unwrap() {
-                    0 => continue,
-                    1 => queue.push_back(next_state),
-                    2 => return Ok(next_state),
-                    _ => unreachable!(),
-                },
+            if next_state.inst.run_until_outputs(1)? != RunOutcome::Matched {
+                continue;
+            }
+            match next_state.inst.pop_output().unwrap() {
+                0 => continue,
+                1 => queue.push_back(next_state),
+                2 => return Ok(next_state),
+                _ => unreachable!(),
             }
         }
     }
@@ -98,13 +98,13 @@ fn q2(mut start_state: SearchState) -> Result<usize> {
             next_state.loc = next_loc;
             next_state.depth += 1;
             next_state.inst.push_input(input.into());
-            match next_state.inst.step()? {
-                StepResult::Halt | StepResult::WaitInput => continue,
-                StepResult::Output => match next_state.inst.pop_output().unwrap() {
-                    0 => continue,
-                    1 | 2 => queue.push_back(next_state),
-                    _ => unreachable!(),
-                },
+            if next_state.inst.run_until_outputs(1)? != RunOutcome::Matched {
+                continue;
+            }
+            match next_state.inst.pop_output().unwrap() {
+                0 => continue,
+                1 | 2 => queue.push_back(next_state),
+                _ => unreachable!(),
             }
         }
     }
@@ -1,3 +1,4 @@
+use aoc2019::computer::ascii::AsciiMachine;
 use aoc2019::computer::*;
 use aoc2019::*;
 use std::collections::{HashMap, HashSet};
@@ -299,15 +300,17 @@ fn main() -> Result<()> {
     let fn_c_input = fn_c.to_input();
 
     prog.0[0] = 2;
-    let mut robot = prog.start();
-    robot.push_inputs(&main_input);
-    robot.push_inputs(&fn_a_input);
-    robot.push_inputs(&fn_b_input);
-    robot.push_inputs(&fn_c_input);
-    robot.push_input('n' as Int);
-    robot.push_input(10);
-    let output = robot.execute()?;
-    println!("ans2={:?}", output.last().unwrap());
+    let mut robot = AsciiMachine::new(prog.start());
+    robot.send_raw(&main_input);
+    robot.send_raw(&fn_a_input);
+    robot.send_raw(&fn_b_input);
+    robot.send_raw(&fn_c_input);
+    robot.send_line("n");
+    let output = robot.read_to_end()?;
+    println!(
+        "ans2={:?}",
+        output.answers.last().context("no dust collected")?
+    );
 
     Ok(())
 }
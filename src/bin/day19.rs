@@ -1,25 +1,25 @@
 use aoc2019::computer::*;
 use aoc2019::*;
 
-fn get(prog: &Program, x: i32, y: i32) -> bool {
-    let out = prog
-        .start_with_input(&[x as Int, y as Int])
-        .execute()
-        .unwrap();
+fn get(drone: &Instant, x: i32, y: i32) -> bool {
+    let mut drone = drone.clone();
+    drone.push_inputs(&[x as Int, y as Int]);
+    let out = drone.execute().unwrap();
     out[0] == 1
 }
 
-fn check(prog: &Program, x: i32, y: i32, side_len: i32) -> bool {
-    get(&prog, x + side_len - 1, y) && get(&prog, x, y + side_len - 1)
+fn check(drone: &Instant, x: i32, y: i32, side_len: i32) -> bool {
+    get(drone, x + side_len - 1, y) && get(drone, x, y + side_len - 1)
 }
 
 fn main() -> Result<()> {
     let prog = Program::load_from_input("day19.txt")?;
+    let drone = prog.start().with_decode_cache();
 
     let mut ans1 = 0;
     for x in 0..50 {
         for y in 0..50 {
-            if get(&prog, x, y) {
+            if get(&drone, x, y) {
                 ans1 += 1;
             }
         }
@@ -31,15 +31,15 @@ fn main() -> Result<()> {
     let mut x = 0;
     let mut y = offset;
     loop {
-        x = (x..).find(|&x| get(&prog, x, y)).unwrap();
-        if get(&prog, x + offset, y - offset) {
+        x = (x..).find(|&x| get(&drone, x, y)).unwrap();
+        if get(&drone, x + offset, y - offset) {
             break;
         }
         y += 1;
     }
     y -= offset;
 
-    assert!(check(&prog, x, y, side_len));
+    assert!(check(&drone, x, y, side_len));
     println!("ans2={}", x * 10_000 + y);
 
     Ok(())
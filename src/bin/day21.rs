@@ -1,27 +1,19 @@
+use aoc2019::computer::ascii::AsciiMachine;
 use aoc2019::computer::*;
 use aoc2019::*;
 
 fn run(prog: &Program, input: &str, echo: bool) -> Result<Int> {
-    let input: Vec<_> = input
-        .split('\n')
-        .map(|l| l.trim())
-        .filter(|l| !l.is_empty())
-        .map(|l| format!("{}\n", l))
-        .join("")
-        .as_bytes()
-        .iter()
-        .map(|&b| b as Int)
-        .collect();
-    let mut instnt = prog.start_with_input(&input);
+    let mut droid = AsciiMachine::new(prog.start());
+    for line in input.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        droid.send_line(line);
+    }
 
-    let out = instnt.execute()?;
+    let out = droid.read_to_end()?;
     if echo {
-        for &v in &out {
-            print!("{}", v as u8 as char);
-        }
+        print!("{}", out.text);
     }
 
-    Ok(*out.last().unwrap())
+    out.answers.last().copied().context("droid fell into space")
 }
 
 fn main() -> Result<()> {
@@ -1,125 +1,62 @@
+use aoc2019::computer::network::{Control, Network, Outbox, Router};
 use aoc2019::computer::*;
 use aoc2019::*;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 struct Packet {
-    dst: Int,
     x: Int,
     y: Int,
 }
 
-struct Controller(Instant);
-
-impl Controller {
-    fn new(prog: &Program, addr: i64) -> Self {
-        Self(prog.start_with_input(&[addr, -1]))
-    }
-
-    fn send(&mut self, p: Packet) {
-        self.0.push_input(p.x);
-        self.0.push_input(p.y);
-    }
-
-    fn send_if_empty(&mut self) {
-        if self.0.input.is_empty() {
-            self.0.push_input(-1);
-        }
-    }
-
-    fn output(&mut self) -> Result<Vec<Packet>> {
-        while self.0.step()? != StepResult::WaitInput {}
-        let mut out = Vec::new();
-        for mut data in &self.0.output_iter().chunks(3) {
-            out.push(Packet {
-                dst: *data.next().context("failed to get dst")?,
-                x: *data.next().context("failed to get x")?,
-                y: *data.next().context("failed to get y")?,
-            });
-        }
-        self.0.clear_output();
-        Ok(out)
-    }
-}
-
-struct Network {
-    controllers: Vec<Controller>,
-    nat: Option<Packet>,
+#[derive(Debug, Default)]
+struct Nat {
+    packet: Option<Packet>,
+    first_received: Option<Packet>,
+    last_sent: Option<Packet>,
+    repeated: Option<Packet>,
 }
 
-enum NatState {
-    Receive(Packet),
-    Send(Packet),
-}
+impl Router for Nat {
+    fn packet_size(&self) -> usize {
+        3
+    }
 
-impl Network {
-    fn new(prog: &Program, size: Int) -> Self {
-        let mut controllers: Vec<Controller> = Vec::with_capacity(50);
-        for i in 0..size {
-            controllers.push(Controller::new(&prog, i));
-        }
-        Self {
-            controllers,
-            nat: None,
+    fn route(&mut self, outbox: &mut Outbox, _src: usize, packet: &[Int]) -> Result<Control> {
+        let (dst, x, y) = (packet[0], packet[1], packet[2]);
+        if dst == 255 {
+            let p = Packet { x, y };
+            self.packet = Some(p);
+            self.first_received.get_or_insert(p);
+        } else {
+            outbox.send(dst as usize, &[x, y])?;
         }
+        Ok(Control::Continue)
     }
 
-    fn run(&mut self) -> Result<NatState> {
-        let mut out: Option<NatState> = None;
-        loop {
-            let mut packets: Vec<Packet> = Vec::new();
-            for c in &mut self.controllers {
-                let mut c_packets = c.output()?;
-                packets.append(&mut c_packets);
-            }
-
-            if packets.is_empty() {
-                let p = self.nat.context("nat is empty")?;
-                self.controllers[0].send(p);
-                out = Some(NatState::Send(p));
-            } else {
-                for p in packets {
-                    if p.dst == 255 {
-                        self.nat = Some(p);
-                        out = Some(NatState::Receive(p));
-                    } else {
-                        self.controllers[p.dst as usize].send(p);
-                    }
-                }
-            }
-
-            for c in &mut self.controllers {
-                c.send_if_empty();
-            }
-
-            if let Some(out) = out {
-                return Ok(out);
-            }
+    fn idle(&mut self, outbox: &mut Outbox) -> Result<Control> {
+        let p = self.packet.context("nat is empty")?;
+        if self.last_sent == Some(p) {
+            self.repeated = Some(p);
+            return Ok(Control::Stop);
         }
+        outbox.send(0, &[p.x, p.y])?;
+        self.last_sent = Some(p);
+        Ok(Control::Continue)
     }
 }
 
 fn main() -> Result<()> {
     let prog = Program::load_from_input("day23.txt")?;
-    let mut network = Network::new(&prog, 50);
+    let mut network = Network::new().with_idle_input(-1);
+    for addr in 0..50 {
+        network.add(prog.start_with_input(&[addr]).with_decode_cache());
+    }
 
-    let p = match network.run()? {
-        NatState::Receive(p) => p,
-        _ => unreachable!(),
-    };
+    let nat = network.run(Nat::default())?;
+    let p = nat.first_received.context("nothing sent to nat")?;
     println!("ans1={:?}", p.y);
-
-    let mut last_send: Option<Packet> = None;
-    loop {
-        let p = match network.run()? {
-            NatState::Receive(_) => continue,
-            NatState::Send(p) => p,
-        };
-        if last_send == Some(p) {
-            println!("ans2={:?}", p.y);
-            break;
-        }
-        last_send = Some(p);
-    }
+    let p = nat.repeated.context("nat never repeated")?;
+    println!("ans2={:?}", p.y);
 
     Ok(())
 }
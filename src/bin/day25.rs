@@ -1,3 +1,4 @@
+use aoc2019::computer::ascii::{AsciiError, AsciiMachine};
 use aoc2019::computer::*;
 use aoc2019::*;
 use std::collections::{HashSet, VecDeque};
@@ -13,14 +14,13 @@ enum Direction {
 use Direction::*;
 
 impl Direction {
-    fn to_move_cmd(self) -> String {
+    fn to_move_cmd(self) -> &'static str {
         match self {
-            N => "north\n",
-            S => "south\n",
-            E => "east\n",
-            W => "west\n",
+            N => "north",
+            S => "south",
+            E => "east",
+            W => "west",
         }
-        .to_owned()
     }
 }
 
@@ -73,7 +73,7 @@ impl TryFrom<&str> for Location {
                         doors.push(Direction::try_from(l.replace("- ", "").as_str())?);
                         Ok(())
                     })
-                    .collect::<Result<_>>()?;
+                    .collect::<Result<()>>()?;
             } else if line == "Items here:" {
                 lines.take_while_ref(|l| l.starts_with('-')).for_each(|l| {
                     items.push(l.replace("- ", ""));
@@ -91,7 +91,7 @@ impl TryFrom<&str> for Location {
 
 #[derive(Clone)]
 struct Droid {
-    instnt: Instant,
+    machine: AsciiMachine,
     loc: Location,
     items: Vec<String>,
 }
@@ -99,7 +99,7 @@ struct Droid {
 impl Droid {
     fn new(prog: &Program) -> Result<Self> {
         let mut droid = Self {
-            instnt: prog.start(),
+            machine: AsciiMachine::new(prog.start()),
             loc: Default::default(),
             items: Vec::new(),
         };
@@ -109,37 +109,15 @@ impl Droid {
     }
 
     fn reponse(&mut self) -> Result<String> {
-        loop {
-            match self.instnt.step()? {
-                StepResult::Halt => {
-                    let out: String = self
-                        .instnt
-                        .output_iter()
-                        .map(|c| *c as u8 as char)
-                        .collect();
-                    println!("{}", out);
-                    bail!("halt");
-                }
-                StepResult::WaitInput => break,
-                StepResult::Output => continue,
-            }
-        }
-        let out = self
-            .instnt
-            .output_iter()
-            .map(|c| *c as u8 as char)
-            .collect();
-        self.instnt.clear_output();
-        Ok(out)
+        Ok(self.machine.read_until_prompt("Command?")?.text)
     }
 
     fn command(&mut self, input: &str) {
-        let input: Vec<Int> = input.as_bytes().iter().map(|c| *c as Int).collect();
-        self.instnt.push_inputs(&input);
+        self.machine.send_line(input);
     }
 
     fn move_to(&mut self, d: Direction) -> Result<Location> {
-        self.command(d.to_move_cmd().as_str());
+        self.command(d.to_move_cmd());
         let out = self.reponse()?;
         let loc = Location::try_from(out.as_str())?;
         self.loc = loc.clone();
@@ -155,14 +133,14 @@ impl Droid {
         {
             self.items.push(item.to_owned());
             self.items.sort();
-            self.command(format!("take {}\n", item).as_str());
+            self.command(&format!("take {}", item));
             self.reponse()?;
         }
         Ok(())
     }
 
     fn drop(&mut self, item: &str) -> Result<()> {
-        self.command(format!("drop {}\n", item).as_str());
+        self.command(&format!("drop {}", item));
         self.reponse()?;
         Ok(())
     }
@@ -200,12 +178,21 @@ fn main() -> Result<()> {
                 d.drop(item)?;
             }
         }
-        d.command("north\n");
-        if let Ok(out) = d.reponse() {
-            if !out.contains("Security Checkpoint") {
-                println!("{}", out);
-                break;
+        d.command("north");
+        match d.reponse() {
+            Ok(out) => {
+                if !out.contains("Security Checkpoint") {
+                    println!("{}", out);
+                    break;
+                }
             }
+            Err(e) => match e.downcast_ref::<AsciiError>() {
+                Some(AsciiError::UnexpectedHalt(out)) => {
+                    println!("{}", out.text);
+                    break;
+                }
+                _ => return Err(e),
+            },
         }
     }
 
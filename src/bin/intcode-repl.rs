@@ -0,0 +1,131 @@
+use aoc2019::computer::ascii::{AsciiMachine, AsciiOutput};
+use aoc2019::computer::*;
+use aoc2019::*;
+
+const HELP: &str = "\
+:save <file>    save the machine to a snapshot file
+:load <file>    replace the machine with a saved snapshot
+:undo           revert the last input
+:history        list the inputs sent so far
+:raw <int>...   send raw values instead of text
+:help           show this message
+:quit           exit";
+
+struct Repl {
+    machine: AsciiMachine,
+    /// Every input sent, with the machine as it was right before.
+    history: Vec<(String, AsciiMachine)>,
+}
+
+impl Repl {
+    fn print(out: &AsciiOutput) {
+        print!("{}", out.text);
+        for answer in &out.answers {
+            println!("[answer: {}]", answer);
+        }
+    }
+
+    fn run(&mut self) -> Result<()> {
+        while let Some(out) = self.machine.read_line()? {
+            Repl::print(&out);
+            io::stdout().flush()?;
+        }
+        if self.machine.is_halted() {
+            println!("[halted]");
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, entry: String, input: &[Int]) -> Result<()> {
+        if self.machine.is_halted() {
+            bail!("machine has halted, use :undo or :load");
+        }
+        self.history.push((entry, self.machine.clone()));
+        self.machine.send_raw(input);
+        self.run()
+    }
+
+    fn meta(&mut self, cmd: &str, args: &str) -> Result<()> {
+        match cmd {
+            "save" => {
+                let path = args
+                    .split_whitespace()
+                    .next()
+                    .context("missing file name")?;
+                self.machine.instant().save(path)?;
+                println!("[saved to {}]", path);
+            }
+            "load" => {
+                let path = args
+                    .split_whitespace()
+                    .next()
+                    .context("missing file name")?;
+                let instant = Instant::load(path)?;
+                self.history
+                    .push((format!(":load {}", path), self.machine.clone()));
+                self.machine = AsciiMachine::new(instant);
+                self.run()?;
+            }
+            "undo" => {
+                let (entry, machine) = self.history.pop().context("nothing to undo")?;
+                self.machine = machine;
+                println!("[undid {}]", entry.trim_end());
+            }
+            "history" => {
+                for (i, (entry, _)) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, entry.trim_end());
+                }
+            }
+            "raw" => {
+                let vals = args
+                    .split_whitespace()
+                    .map(|v| {
+                        v.parse::<Int>()
+                            .with_context(|| format!("invalid value {}", v))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                if vals.is_empty() {
+                    bail!("missing values");
+                }
+                self.send(format!(":raw {}", vals.iter().join(" ")), &vals)?;
+            }
+            "help" => println!("{}", HELP),
+            _ => bail!("unknown command :{}, try :help", cmd),
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let file = std::env::args()
+        .nth(1)
+        .context("usage: intcode-repl <file in data/>")?;
+    let prog = Program::load(&file)?;
+    let mut repl = Repl {
+        machine: AsciiMachine::new(prog.start()),
+        history: Vec::new(),
+    };
+    if let Err(e) = repl.run() {
+        println!("[error: {}]", e);
+    }
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let result = if let Some(meta) = line.trim().strip_prefix(':') {
+            let (cmd, args) = meta.split_at(meta.find(' ').unwrap_or(meta.len()));
+            if cmd == "quit" {
+                break;
+            }
+            repl.meta(cmd, args)
+        } else {
+            let input: Vec<Int> = line.bytes().chain(Some(b'\n')).map(Int::from).collect();
+            repl.send(line, &input)
+        };
+        if let Err(e) = result {
+            println!("[error: {}]", e);
+        }
+    }
+
+    Ok(())
+}
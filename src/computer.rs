@@ -1,19 +1,86 @@
 //! Intcode Computer used in Day 05, 07, 09, 11, 13, 15, 17, 19, 21, 23, 25
 
 use crate::*;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryFrom;
+use std::fmt;
 use std::iter::FromIterator;
+use std::sync::Arc;
+
+pub mod ascii;
+pub mod asm;
+pub mod asyncio;
+mod binary;
+mod cache;
+pub mod cfg;
+pub mod compiler;
+pub mod decompile;
+pub mod disasm;
+mod extension;
+mod memory;
+pub mod network;
+mod parse;
+mod policy;
+mod ports;
+mod profile;
+mod run;
+mod snapshot;
+pub mod topology;
+mod undo;
+mod watch;
+mod watchdog;
+mod word;
+
+use self::cache::{DecodeCache, StaleEntries};
+use self::extension::{Extension, Fetched};
+pub use self::extension::{Handler, Params};
+pub use self::memory::{DenseMemory, Memory, MemoryKind, PagedMemory, SparseMemory, PAGE_SIZE};
+pub use self::parse::ParseError;
+pub use self::policy::{ExecutionPolicy, OverflowMode};
+pub use self::ports::{InputIter, InputSource, OutputSink, OutputWriter};
+pub use self::profile::{HotLoop, Profile};
+pub use self::run::RunOutcome;
+use self::undo::UndoLog;
+pub use self::undo::{MemoryWrite, UndoRecord};
+use self::watch::Watchpoints;
+pub use self::watch::{Access, WatchHit};
+pub use self::watchdog::BudgetedStep;
+use self::watchdog::LoopDetector;
+pub use self::word::{CheckedInt, Word};
 
 pub type Int = i64;
 
-pub struct Program(pub Vec<Int>);
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Program<W = Int>(pub Vec<W>);
+
+impl<W: Word> Program<W> {
+    /// Program over any word type, see `new` for one of `Int` words.
+    pub fn from_words(code: Vec<W>) -> Self {
+        Self(code)
+    }
+
+    pub fn start(&self) -> Instant<W> {
+        self.start_with_memory(MemoryKind::default())
+    }
+
+    pub fn start_with_memory(&self, kind: MemoryKind) -> Instant<W> {
+        Instant::new(kind.create(&self.0))
+    }
+
+    pub fn start_with_input(&self, input: &[W]) -> Instant<W> {
+        let mut instant = self.start();
+        instant.input = VecDeque::from_iter(input.iter().cloned());
+        instant
+    }
+}
 
 impl Program {
     pub fn new(code: Vec<Int>) -> Self {
         Self(code)
     }
 
+    /// Lenient loader which skips anything that is not a number, see
+    /// `Program::parse` for a strict one.
     pub fn load_from_str(code: &str) -> Self {
         Self(
             code.trim()
@@ -26,67 +93,219 @@ impl Program {
     pub fn load_from_input(path: &str) -> Result<Self> {
         let mut reader = open_input(path)?;
         let mut buf = String::new();
-        reader.read_line(&mut buf)?;
-        Ok(Self::load_from_str(&buf))
+        reader.read_to_string(&mut buf)?;
+        Self::parse(&buf).with_context(|| format!("failed to load {}", path))
     }
 
-    pub fn start(&self) -> Instant {
-        Instant {
-            pc: 0,
-            base: 0,
-            mem: self.0.clone(),
-            input: VecDeque::new(),
-            output: VecDeque::new(),
-        }
+    pub fn assemble(src: &str) -> Result<Self> {
+        asm::assemble(src)
     }
 
-    pub fn start_with_input(&self, input: &[Int]) -> Instant {
-        Instant {
-            pc: 0,
-            base: 0,
-            mem: self.0.clone(),
-            input: VecDeque::from_iter(input.iter().cloned()),
-            output: VecDeque::new(),
-        }
+    pub fn disassemble(&self) -> String {
+        disasm::disassemble(self)
+            .iter()
+            .map(|stmt| format!("{:>5}: {}\n", stmt.addr(), stmt))
+            .collect()
+    }
+
+    /// The same program over another word type, e.g. to run it with
+    /// `CheckedInt` or `BigInt` words.
+    pub fn convert<W: Word>(&self) -> Program<W> {
+        Program(self.0.iter().map(|&val| W::from_int(val)).collect())
     }
 }
 
 #[derive(Clone)]
-pub struct Instant {
+pub struct Instant<W: Word = Int> {
     pub pc: usize,
-    pub base: Int,
-    pub mem: Vec<Int>,
-    pub input: VecDeque<Int>,
-    pub output: VecDeque<Int>,
+    pub base: W,
+    pub mem: Box<dyn Memory<W>>,
+    pub input: VecDeque<W>,
+    pub output: VecDeque<W>,
+    pub policy: ExecutionPolicy,
+    pub warnings: Vec<ComputerError>,
+    decode_cache: Option<Arc<DecodeCache<W>>>,
+    stale_code: StaleEntries,
+    /// Set while any of the hooks below is enabled, so that the plain
+    /// interpreter only checks this flag.
+    instrumented: bool,
+    undo: Option<UndoLog<W>>,
+    loop_detector: Option<LoopDetector<W>>,
+    profile: Option<Box<Profile>>,
+    watch: Option<Box<Watchpoints<W>>>,
+    extensions: HashMap<Int, Extension<W>>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-pub enum StepResult {
+pub enum StepResult<W = Int> {
     Output,
     WaitInput,
     Halt,
+    /// An instruction touched a watched address.
+    Watch(WatchHit<W>),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ComputerError {
+    InvalidOpCode {
+        pc: usize,
+        word: Int,
+        base: Int,
+    },
+    InvalidMode {
+        pc: usize,
+        word: Int,
+        base: Int,
+        mode: Int,
+    },
+    InputExhausted {
+        pc: usize,
+        word: Int,
+        base: Int,
+    },
+    BadAddress {
+        pc: usize,
+        word: Int,
+        base: Int,
+        addr: Int,
+    },
+    ImmediateWrite {
+        pc: usize,
+        word: Int,
+        base: Int,
+    },
+    MemoryLimit {
+        pc: usize,
+        word: Int,
+        base: Int,
+        addr: Int,
+    },
+    Overflow {
+        pc: usize,
+        word: Int,
+        base: Int,
+    },
+    InfiniteLoop {
+        pc: usize,
+        word: Int,
+        base: Int,
+    },
+    OutputClosed {
+        pc: usize,
+        word: Int,
+        base: Int,
+    },
+}
+
+pub type ComputerResult<T> = std::result::Result<T, ComputerError>;
+
+impl ComputerError {
+    pub fn pc(&self) -> usize {
+        match *self {
+            ComputerError::InvalidOpCode { pc, .. }
+            | ComputerError::InvalidMode { pc, .. }
+            | ComputerError::InputExhausted { pc, .. }
+            | ComputerError::BadAddress { pc, .. }
+            | ComputerError::ImmediateWrite { pc, .. }
+            | ComputerError::MemoryLimit { pc, .. }
+            | ComputerError::Overflow { pc, .. }
+            | ComputerError::InfiniteLoop { pc, .. }
+            | ComputerError::OutputClosed { pc, .. } => pc,
+        }
+    }
+
+    pub fn word(&self) -> Int {
+        match *self {
+            ComputerError::InvalidOpCode { word, .. }
+            | ComputerError::InvalidMode { word, .. }
+            | ComputerError::InputExhausted { word, .. }
+            | ComputerError::BadAddress { word, .. }
+            | ComputerError::ImmediateWrite { word, .. }
+            | ComputerError::MemoryLimit { word, .. }
+            | ComputerError::Overflow { word, .. }
+            | ComputerError::InfiniteLoop { word, .. }
+            | ComputerError::OutputClosed { word, .. } => word,
+        }
+    }
+
+    pub fn base(&self) -> Int {
+        match *self {
+            ComputerError::InvalidOpCode { base, .. }
+            | ComputerError::InvalidMode { base, .. }
+            | ComputerError::InputExhausted { base, .. }
+            | ComputerError::BadAddress { base, .. }
+            | ComputerError::ImmediateWrite { base, .. }
+            | ComputerError::MemoryLimit { base, .. }
+            | ComputerError::Overflow { base, .. }
+            | ComputerError::InfiniteLoop { base, .. }
+            | ComputerError::OutputClosed { base, .. } => base,
+        }
+    }
+}
+
+impl fmt::Display for ComputerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComputerError::InvalidOpCode { .. } => write!(f, "invalid op code {}", self.word()),
+            ComputerError::InvalidMode { mode, .. } => {
+                write!(f, "invalid mode {} in {}", mode, self.word())
+            }
+            ComputerError::InputExhausted { .. } => write!(f, "failed to get input"),
+            ComputerError::BadAddress { addr, .. } => write!(f, "bad address {}", addr),
+            ComputerError::ImmediateWrite { .. } => write!(f, "write in immediate mode"),
+            ComputerError::MemoryLimit { addr, .. } => {
+                write!(f, "write to {} exceeds memory limit", addr)
+            }
+            ComputerError::Overflow { .. } => write!(f, "arithmetic overflow"),
+            ComputerError::InfiniteLoop { .. } => write!(f, "stuck in an infinite loop"),
+            ComputerError::OutputClosed { .. } => write!(f, "output closed"),
+        }?;
+        write!(f, " (pc={}, base={})", self.pc(), self.base())
+    }
+}
+
+impl std::error::Error for ComputerError {}
+
+/// Failure to decode a single instruction word, independent of any machine.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DecodeError {
+    InvalidOpCode(Int),
+    InvalidMode(Int),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidOpCode(code) => write!(f, "invalid op code {}", code),
+            DecodeError::InvalidMode(mode) => write!(f, "invalid mode {}", mode),
+        }
+    }
 }
 
-enum ParameterMode {
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ParameterMode {
     Immediate,
     Position,
     Relative,
 }
 
 impl TryFrom<Int> for ParameterMode {
-    type Error = Error;
+    type Error = DecodeError;
 
     fn try_from(value: Int) -> std::result::Result<Self, Self::Error> {
         match value {
             0 => Ok(ParameterMode::Position),
             1 => Ok(ParameterMode::Immediate),
             2 => Ok(ParameterMode::Relative),
-            _ => bail!("invalid mode {}", value),
+            _ => Err(DecodeError::InvalidMode(value)),
         }
     }
 }
 
-enum OpCode {
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OpCode {
     Add(ParameterMode, ParameterMode, ParameterMode),
     Mul(ParameterMode, ParameterMode, ParameterMode),
     Input(ParameterMode),
@@ -100,7 +319,7 @@ enum OpCode {
 }
 
 impl TryFrom<Int> for OpCode {
-    type Error = Error;
+    type Error = DecodeError;
 
     fn try_from(code: Int) -> std::result::Result<Self, Self::Error> {
         let m1 = ParameterMode::try_from((code / 100) % 10)?;
@@ -118,130 +337,570 @@ impl TryFrom<Int> for OpCode {
             8 => Ok(OpCode::Equal(m1, m2, m3)),
             9 => Ok(OpCode::AdjustBase(m1)),
             99 => Ok(OpCode::Halt),
-            _ => bail!("invalid op code {}", code),
+            _ => Err(DecodeError::InvalidOpCode(code)),
         }
     }
 }
 
-impl Instant {
-    fn read(&self, addr: usize) -> Int {
-        match self.mem.get(addr).copied() {
-            Some(val) => val,
-            None => 0,
+/// An instruction decoded together with its raw parameter words.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Decoded<W> {
+    op: OpCode,
+    args: [W; 3],
+}
+
+impl ParameterMode {
+    pub fn digit(self) -> Int {
+        match self {
+            ParameterMode::Position => 0,
+            ParameterMode::Immediate => 1,
+            ParameterMode::Relative => 2,
         }
     }
+}
 
-    fn deref_read(&self, addr: usize, base: Int) -> Int {
-        self.read((self.read(addr) + base) as usize)
+impl OpCode {
+    pub fn code(self) -> Int {
+        match self {
+            OpCode::Add(..) => 1,
+            OpCode::Mul(..) => 2,
+            OpCode::Input(..) => 3,
+            OpCode::Output(..) => 4,
+            OpCode::JumpIfTrue(..) => 5,
+            OpCode::JumpIfFalse(..) => 6,
+            OpCode::LessThan(..) => 7,
+            OpCode::Equal(..) => 8,
+            OpCode::AdjustBase(..) => 9,
+            OpCode::Halt => 99,
+        }
     }
 
-    fn write(&mut self, addr: usize, val: Int) {
-        if addr >= self.mem.len() {
-            self.mem.resize(addr + 1, 0);
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            OpCode::Add(..) => "ADD",
+            OpCode::Mul(..) => "MUL",
+            OpCode::Input(..) => "IN",
+            OpCode::Output(..) => "OUT",
+            OpCode::JumpIfTrue(..) => "JT",
+            OpCode::JumpIfFalse(..) => "JF",
+            OpCode::LessThan(..) => "LT",
+            OpCode::Equal(..) => "EQ",
+            OpCode::AdjustBase(..) => "ARB",
+            OpCode::Halt => "HLT",
         }
-        self.mem[addr] = val;
     }
 
-    fn deref_write(&mut self, addr: usize, base: Int, val: Int) {
-        self.write((self.read(addr) + base) as usize, val)
+    pub fn modes(self) -> Vec<ParameterMode> {
+        match self {
+            OpCode::Add(m1, m2, m3)
+            | OpCode::Mul(m1, m2, m3)
+            | OpCode::LessThan(m1, m2, m3)
+            | OpCode::Equal(m1, m2, m3) => vec![m1, m2, m3],
+            OpCode::JumpIfTrue(m1, m2) | OpCode::JumpIfFalse(m1, m2) => vec![m1, m2],
+            OpCode::Input(m1) | OpCode::Output(m1) | OpCode::AdjustBase(m1) => vec![m1],
+            OpCode::Halt => vec![],
+        }
     }
 
-    fn read_parameter(&self, idx: usize, mode: ParameterMode) -> Int {
-        match mode {
-            ParameterMode::Position => self.deref_read(self.pc + idx, 0),
-            ParameterMode::Immediate => self.read(self.pc + idx),
-            ParameterMode::Relative => self.deref_read(self.pc + idx, self.base),
+    pub fn size(self) -> usize {
+        match self {
+            OpCode::Add(..) | OpCode::Mul(..) | OpCode::LessThan(..) | OpCode::Equal(..) => 4,
+            OpCode::JumpIfTrue(..) | OpCode::JumpIfFalse(..) => 3,
+            OpCode::Input(..) | OpCode::Output(..) | OpCode::AdjustBase(..) => 2,
+            OpCode::Halt => 1,
         }
     }
 
-    fn write_parameter(&mut self, idx: usize, mode: ParameterMode, val: Int) {
-        match mode {
-            ParameterMode::Position => self.deref_write(self.pc + idx, 0, val),
-            ParameterMode::Immediate => self.write(self.pc + idx, val),
-            ParameterMode::Relative => self.deref_write(self.pc + idx, self.base, val),
+    pub fn encode(self) -> Int {
+        self.modes()
+            .iter()
+            .rev()
+            .fold(0, |acc, m| acc * 10 + m.digit())
+            * 100
+            + self.code()
+    }
+}
+
+impl<W: Word> Instant<W> {
+    pub fn new(mem: Box<dyn Memory<W>>) -> Self {
+        Self {
+            pc: 0,
+            base: W::default(),
+            mem,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            policy: ExecutionPolicy::default(),
+            warnings: Vec::new(),
+            decode_cache: None,
+            stale_code: StaleEntries::default(),
+            instrumented: false,
+            undo: None,
+            loop_detector: None,
+            profile: None,
+            watch: None,
+            extensions: HashMap::new(),
         }
     }
 
-    pub fn step(&mut self) -> Result<StepResult> {
-        loop {
-            match OpCode::try_from(self.read(self.pc))? {
-                OpCode::Add(m1, m2, m3) => {
-                    let val1 = self.read_parameter(1, m1);
-                    let val2 = self.read_parameter(2, m2);
-                    let val3 = val1 + val2;
-                    self.write_parameter(3, m3, val3);
-                    self.pc += 4;
+    fn read(&self, addr: usize) -> W {
+        self.mem.read(addr)
+    }
+
+    fn deref_read(&self, ptr: &W, base: &W) -> ComputerResult<W> {
+        let target = ptr.overflowing_add(base).0.saturate();
+        if target < 0 {
+            if self.policy.reject_negative_address {
+                return Err(self.bad_address(target));
+            }
+            return Ok(W::default());
+        }
+        Ok(self.read(target as usize))
+    }
+
+    fn write(&mut self, addr: usize, val: W) -> ComputerResult<()> {
+        self.stale_code.invalidate(addr);
+        if let Some(limit) = self.policy.memory_limit {
+            if addr >= limit && addr >= self.mem.len() {
+                return Err(ComputerError::MemoryLimit {
+                    pc: self.pc,
+                    word: self.read(self.pc).saturate(),
+                    base: self.base.saturate(),
+                    addr: addr as Int,
+                });
+            }
+        }
+        if self.instrumented {
+            self.record_write(addr, &val);
+        }
+        self.mem.write(addr, val);
+        Ok(())
+    }
+
+    fn record_write(&mut self, addr: usize, val: &W) {
+        let old = self.mem.read(addr);
+        if let Some(detector) = &mut self.loop_detector {
+            detector.record_write(addr, &old, val);
+        }
+        if let Some(watch) = &mut self.watch {
+            watch.record_write(self.pc, addr, &old, val);
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.record_write(addr);
+        }
+        if let Some(log) = &mut self.undo {
+            log.record_write(addr, old, val.clone());
+        }
+    }
+
+    /// Feed the reads `ins` is about to make to the profiler and watchpoints.
+    /// Built-in instructions read all their parameters before writing.
+    fn record_reads(&mut self, ins: &Decoded<W>) {
+        let count = match ins.op {
+            OpCode::Add(..) | OpCode::Mul(..) | OpCode::LessThan(..) | OpCode::Equal(..) => 2,
+            OpCode::Output(..) | OpCode::AdjustBase(..) => 1,
+            OpCode::JumpIfTrue(m1, _) | OpCode::JumpIfFalse(m1, _) => {
+                let jump_if = matches!(ins.op, OpCode::JumpIfTrue(..));
+                match self.read_arg(&ins.args[0], m1) {
+                    Ok(val) if val.is_zero() != jump_if => 2,
+                    _ => 1,
                 }
-                OpCode::Mul(m1, m2, m3) => {
-                    let val1 = self.read_parameter(1, m1);
-                    let val2 = self.read_parameter(2, m2);
-                    let val3 = val1 * val2;
-                    self.write_parameter(3, m3, val3);
-                    self.pc += 4;
+            }
+            OpCode::Input(..) | OpCode::Halt => 0,
+        };
+        for (arg, mode) in ins.args.iter().zip(ins.op.modes()).take(count) {
+            if let Some(addr) = self.arg_address(arg, mode) {
+                self.record_read(addr);
+            }
+        }
+    }
+
+    fn record_read(&mut self, addr: usize) {
+        if let Some(profile) = &mut self.profile {
+            profile.record_read(addr);
+        }
+        if let Some(watch) = &mut self.watch {
+            let val = self.mem.read(addr);
+            watch.record_read(self.pc, addr, &val);
+        }
+    }
+
+    fn record_input(&mut self, val: W) {
+        if let Some(log) = &mut self.undo {
+            log.record_input(val);
+        }
+        if let Some(detector) = &mut self.loop_detector {
+            detector.reset();
+        }
+    }
+
+    fn record_output(&mut self, val: W) {
+        if let Some(log) = &mut self.undo {
+            log.record_output(val);
+        }
+        if let Some(detector) = &mut self.loop_detector {
+            detector.reset();
+        }
+    }
+
+    /// Recompute `instrumented` after enabling or disabling a hook.
+    fn update_instrumented(&mut self) {
+        self.instrumented = self.undo.is_some()
+            || self.loop_detector.is_some()
+            || self.profile.is_some()
+            || self.watch.is_some();
+    }
+
+    fn deref_write(&mut self, ptr: &W, base: &W, val: W) -> ComputerResult<()> {
+        let target = ptr.overflowing_add(base).0.saturate();
+        if target < 0 {
+            return Err(self.bad_address(target));
+        }
+        self.write(target as usize, val)
+    }
+
+    fn read_parameter(
+        &self,
+        ins: &Decoded<W>,
+        idx: usize,
+        mode: ParameterMode,
+    ) -> ComputerResult<W> {
+        self.read_arg(&ins.args[idx - 1], mode)
+    }
+
+    fn read_arg(&self, arg: &W, mode: ParameterMode) -> ComputerResult<W> {
+        match mode {
+            ParameterMode::Position => self.deref_read(arg, &W::default()),
+            ParameterMode::Immediate => Ok(arg.clone()),
+            ParameterMode::Relative => self.deref_read(arg, &self.base),
+        }
+    }
+
+    /// Address a read through `arg` accesses, if it accesses memory at all.
+    fn arg_address(&self, arg: &W, mode: ParameterMode) -> Option<usize> {
+        let target = match mode {
+            ParameterMode::Position => arg.saturate(),
+            ParameterMode::Immediate => return None,
+            ParameterMode::Relative => arg.overflowing_add(&self.base).0.saturate(),
+        };
+        if target < 0 {
+            return None;
+        }
+        Some(target as usize)
+    }
+
+    fn write_parameter(
+        &mut self,
+        ins: &Decoded<W>,
+        idx: usize,
+        mode: ParameterMode,
+        val: W,
+    ) -> ComputerResult<()> {
+        self.write_arg(&ins.args[idx - 1], self.pc + idx, mode, val)
+    }
+
+    /// Write `val` through the parameter `arg` stored at address `at`.
+    fn write_arg(&mut self, arg: &W, at: usize, mode: ParameterMode, val: W) -> ComputerResult<()> {
+        match mode {
+            ParameterMode::Position => self.deref_write(arg, &W::default(), val),
+            ParameterMode::Immediate => {
+                if self.policy.reject_immediate_write {
+                    return Err(ComputerError::ImmediateWrite {
+                        pc: self.pc,
+                        word: self.read(self.pc).saturate(),
+                        base: self.base.saturate(),
+                    });
                 }
-                OpCode::Input(m1) => {
-                    if let Some(val) = self.input.pop_front() {
-                        self.write_parameter(1, m1, val);
-                        self.pc += 2;
-                    } else {
-                        break Ok(StepResult::WaitInput);
+                self.write(at, val)
+            }
+            ParameterMode::Relative => {
+                let base = self.base.clone();
+                self.deref_write(arg, &base, val)
+            }
+        }
+    }
+
+    fn arith(&mut self, (val, overflow): (W, bool)) -> ComputerResult<W> {
+        if overflow && (W::CHECKED || self.policy.overflow != OverflowMode::Ignore) {
+            let err = ComputerError::Overflow {
+                pc: self.pc,
+                word: self.read(self.pc).saturate(),
+                base: self.base.saturate(),
+            };
+            if W::CHECKED || self.policy.overflow == OverflowMode::Reject {
+                return Err(err);
+            }
+            self.warnings.push(err);
+        }
+        Ok(val)
+    }
+
+    fn jump(&mut self, target: W) -> ComputerResult<()> {
+        let target = target.saturate();
+        if target < 0 {
+            return Err(self.bad_address(target));
+        }
+        self.pc = target as usize;
+        Ok(())
+    }
+
+    fn bad_address(&self, addr: Int) -> ComputerError {
+        ComputerError::BadAddress {
+            pc: self.pc,
+            word: self.read(self.pc).saturate(),
+            base: self.base.saturate(),
+            addr,
+        }
+    }
+
+    fn input_exhausted(&self) -> ComputerError {
+        ComputerError::InputExhausted {
+            pc: self.pc,
+            word: self.read(self.pc).saturate(),
+            base: self.base.saturate(),
+        }
+    }
+
+    fn decode(&self, pc: usize) -> ComputerResult<Decoded<W>> {
+        let word = self.read(pc).saturate();
+        let op = OpCode::try_from(word).map_err(|e| {
+            let base = self.base.saturate();
+            match e {
+                DecodeError::InvalidOpCode(_) => ComputerError::InvalidOpCode { pc, word, base },
+                DecodeError::InvalidMode(mode) => ComputerError::InvalidMode {
+                    pc,
+                    word,
+                    base,
+                    mode,
+                },
+            }
+        })?;
+        let mut args: [W; 3] = Default::default();
+        for (idx, arg) in args.iter_mut().enumerate().take(op.size() - 1) {
+            *arg = self.read(pc + idx + 1);
+        }
+        Ok(Decoded { op, args })
+    }
+
+    fn exec(&mut self, ins: &Decoded<W>) -> ComputerResult<Option<StepResult<W>>> {
+        match ins.op {
+            OpCode::Add(m1, m2, m3) => {
+                let val1 = self.read_parameter(ins, 1, m1)?;
+                let val2 = self.read_parameter(ins, 2, m2)?;
+                let val3 = self.arith(val1.overflowing_add(&val2))?;
+                self.write_parameter(ins, 3, m3, val3)?;
+                self.pc += 4;
+            }
+            OpCode::Mul(m1, m2, m3) => {
+                let val1 = self.read_parameter(ins, 1, m1)?;
+                let val2 = self.read_parameter(ins, 2, m2)?;
+                let val3 = self.arith(val1.overflowing_mul(&val2))?;
+                self.write_parameter(ins, 3, m3, val3)?;
+                self.pc += 4;
+            }
+            OpCode::Input(m1) => {
+                if let Some(val) = self.input.front().cloned() {
+                    self.write_parameter(ins, 1, m1, val)?;
+                    let val = self.input.pop_front().unwrap();
+                    if self.instrumented {
+                        self.record_input(val);
                     }
-                }
-                OpCode::Output(m1) => {
-                    self.output.push_back(self.read_parameter(1, m1));
                     self.pc += 2;
-                    break Ok(StepResult::Output);
-                }
-                OpCode::JumpIfTrue(m1, m2) => {
-                    let val = self.read_parameter(1, m1);
-                    if val != 0 {
-                        self.pc = self.read_parameter(2, m2) as usize;
-                    } else {
-                        self.pc += 3;
-                    }
+                } else {
+                    return Ok(Some(StepResult::WaitInput));
                 }
-                OpCode::JumpIfFalse(m1, m2) => {
-                    let val = self.read_parameter(1, m1);
-                    if val == 0 {
-                        self.pc = self.read_parameter(2, m2) as usize;
-                    } else {
-                        self.pc += 3;
-                    }
+            }
+            OpCode::Output(m1) => {
+                let val = self.read_parameter(ins, 1, m1)?;
+                if self.instrumented {
+                    self.record_output(val.clone());
                 }
-                OpCode::LessThan(m1, m2, m3) => {
-                    let val1 = self.read_parameter(1, m1);
-                    let val2 = self.read_parameter(2, m2);
-                    let val3 = if val1 < val2 { 1 } else { 0 };
-                    self.write_parameter(3, m3, val3);
-                    self.pc += 4;
+                self.output.push_back(val);
+                self.pc += 2;
+                return Ok(Some(StepResult::Output));
+            }
+            OpCode::JumpIfTrue(m1, m2) => {
+                let val = self.read_parameter(ins, 1, m1)?;
+                if !val.is_zero() {
+                    let target = self.read_parameter(ins, 2, m2)?;
+                    self.jump(target)?;
+                } else {
+                    self.pc += 3;
                 }
-                OpCode::Equal(m1, m2, m3) => {
-                    let val1 = self.read_parameter(1, m1);
-                    let val2 = self.read_parameter(2, m2);
-                    let val3 = if val1 == val2 { 1 } else { 0 };
-                    self.write_parameter(3, m3, val3);
-                    self.pc += 4;
+            }
+            OpCode::JumpIfFalse(m1, m2) => {
+                let val = self.read_parameter(ins, 1, m1)?;
+                if val.is_zero() {
+                    let target = self.read_parameter(ins, 2, m2)?;
+                    self.jump(target)?;
+                } else {
+                    self.pc += 3;
                 }
-                OpCode::AdjustBase(m1) => {
-                    self.base += self.read_parameter(1, m1);
-                    self.pc += 2;
+            }
+            OpCode::LessThan(m1, m2, m3) => {
+                let val1 = self.read_parameter(ins, 1, m1)?;
+                let val2 = self.read_parameter(ins, 2, m2)?;
+                let val3 = W::from_int(if val1 < val2 { 1 } else { 0 });
+                self.write_parameter(ins, 3, m3, val3)?;
+                self.pc += 4;
+            }
+            OpCode::Equal(m1, m2, m3) => {
+                let val1 = self.read_parameter(ins, 1, m1)?;
+                let val2 = self.read_parameter(ins, 2, m2)?;
+                let val3 = W::from_int(if val1 == val2 { 1 } else { 0 });
+                self.write_parameter(ins, 3, m3, val3)?;
+                self.pc += 4;
+            }
+            OpCode::AdjustBase(m1) => {
+                let val = self.read_parameter(ins, 1, m1)?;
+                self.base = self.arith(self.base.overflowing_add(&val))?;
+                self.pc += 2;
+            }
+            OpCode::Halt => return Ok(Some(StepResult::Halt)),
+        }
+        Ok(None)
+    }
+
+    fn fetch(&mut self) -> ComputerResult<Fetched<W>> {
+        match self.decode(self.pc) {
+            Ok(ins) => Ok(Fetched::Builtin(ins)),
+            Err(err) => self.fetch_extension(err),
+        }
+    }
+
+    /// Execute a single instruction, returning a result if `step` should stop.
+    fn tick(&mut self) -> ComputerResult<Option<StepResult<W>>> {
+        if self.instrumented {
+            return self.tick_instrumented();
+        }
+        // taken out while executing, writes only need `stale_code`
+        if let Some(cache) = self.decode_cache.take() {
+            let result = match cache.get(self.pc) {
+                Some(ins) if !self.stale_code.contains(self.pc) => Some(self.exec(ins)),
+                _ => None,
+            };
+            self.decode_cache = Some(cache);
+            if let Some(result) = result {
+                return result;
+            }
+        }
+        match self.decode(self.pc) {
+            Ok(ins) => self.exec(&ins),
+            Err(err) => match self.fetch_extension(err)? {
+                Fetched::Builtin(ins) => self.exec(&ins),
+                Fetched::Extension(ext, params) => self.exec_extension(&ext, &params),
+            },
+        }
+    }
+
+    /// `tick` feeding the undo log, loop detector, profiler and watchpoints.
+    #[cold]
+    fn tick_instrumented(&mut self) -> ComputerResult<Option<StepResult<W>>> {
+        // a hit from an instruction which also produced output
+        if let Some(hit) = self.watch.as_mut().and_then(|w| w.hit.take()) {
+            return Ok(Some(StepResult::Watch(hit)));
+        }
+        let ins = self.fetch()?;
+        if let Some(watch) = &mut self.watch {
+            watch.record_execute(self.pc, ins.size());
+        }
+        if let Some(log) = &mut self.undo {
+            log.begin(self.pc, self.base.clone());
+        }
+        let pc = self.pc;
+        if let Fetched::Builtin(ins) = &ins {
+            self.record_reads(ins);
+        }
+        let result = match &ins {
+            Fetched::Builtin(ins) => self.exec(ins),
+            Fetched::Extension(ext, params) => self.exec_extension_logged(ext, params),
+        };
+        if let Some(profile) = &mut self.profile {
+            if let Ok(None) | Ok(Some(StepResult::Output)) = result {
+                profile.record_instruction(pc, ins.mnemonic(), &ins.modes());
+                if self.pc != pc + ins.size() {
+                    profile.record_jump(pc, self.pc);
                 }
-                OpCode::Halt => break Ok(StepResult::Halt),
             }
         }
+        if let Some(log) = &mut self.undo {
+            match result {
+                Ok(None) | Ok(Some(StepResult::Output)) => log.commit(),
+                _ => log.abort(),
+            }
+        }
+        if let Ok(None) = result {
+            self.check_loop()?;
+        }
+        if let Some(watch) = &mut self.watch {
+            match result {
+                Ok(None) => return Ok(watch.hit.take().map(StepResult::Watch)),
+                Ok(Some(StepResult::Output)) => {}
+                _ => watch.hit = None,
+            }
+        }
+        result
+    }
+
+    pub fn step(&mut self) -> ComputerResult<StepResult<W>> {
+        loop {
+            if let Some(result) = self.tick()? {
+                break Ok(result);
+            }
+        }
+    }
+
+    pub fn with_policy(mut self, policy: ExecutionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Pre-decode every word of memory so that execution skips instruction
+    /// parsing. Clones of the resulting `Instant` share the cache, so a
+    /// prepared machine is cheap to clone for each run.
+    ///
+    /// Writes made by the program invalidate the affected entries, but writes
+    /// made directly through `mem` require a call to `refresh_decode_cache`.
+    /// Instructions are decoded as usual while profiling, watching or
+    /// recording undo history.
+    pub fn enable_decode_cache(&mut self) {
+        if self.decode_cache.is_none() {
+            self.build_decode_cache();
+        }
+    }
+
+    pub fn with_decode_cache(mut self) -> Self {
+        self.enable_decode_cache();
+        self
+    }
+
+    /// Rebuild the decode cache from the current contents of `mem`.
+    pub fn refresh_decode_cache(&mut self) {
+        if self.decode_cache.is_some() {
+            self.build_decode_cache();
+        }
+    }
+
+    fn build_decode_cache(&mut self) {
+        // anything beyond the loaded program is decoded on every use
+        let entries = (0..self.mem.len().min(1 << 16))
+            .map(|addr| self.decode(addr).ok())
+            .collect();
+        let cache = DecodeCache::new(entries);
+        self.stale_code = StaleEntries::new(cache.len());
+        self.decode_cache = Some(Arc::new(cache));
     }
 
-    pub fn push_input(&mut self, val: Int) {
+    pub fn push_input(&mut self, val: W) {
         self.input.push_back(val);
     }
 
-    pub fn push_inputs(&mut self, vals: &[Int]) {
+    pub fn push_inputs(&mut self, vals: &[W]) {
         for v in vals {
-            self.input.push_back(*v);
+            self.input.push_back(v.clone());
         }
     }
 
-    pub fn pop_output(&mut self) -> Option<Int> {
+    pub fn pop_output(&mut self) -> Option<W> {
         self.output.pop_front()
     }
 
@@ -249,16 +908,16 @@ impl Instant {
         self.output.clear();
     }
 
-    pub fn output_iter(&self) -> impl Iterator<Item = &Int> {
+    pub fn output_iter(&self) -> impl Iterator<Item = &W> {
         self.output.iter()
     }
 
-    pub fn execute(&mut self) -> Result<Vec<Int>> {
+    pub fn execute(&mut self) -> ComputerResult<Vec<W>> {
         loop {
             match self.step()? {
                 StepResult::Halt => break Ok(Vec::from(self.output.clone())),
-                StepResult::Output => {}
-                StepResult::WaitInput => bail!("failed to get input"),
+                StepResult::Output | StepResult::Watch(_) => {}
+                StepResult::WaitInput => break Err(self.input_exhausted()),
             }
         }
     }
@@ -0,0 +1,196 @@
+//! Text I/O for Intcode programs speaking ASCII
+
+use super::{ComputerError, Instant, Int, StepResult};
+use std::fmt;
+use std::string::FromUtf8Error;
+
+/// Output of an ASCII program, split into text and any trailing non-ASCII
+/// values, which such programs use to report their answer.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct AsciiOutput {
+    pub text: String,
+    pub answers: Vec<Int>,
+}
+
+impl AsciiOutput {
+    fn decode(words: Vec<Int>) -> Result<Self, AsciiError> {
+        let split = words
+            .iter()
+            .rposition(|w| (0..128).contains(w))
+            .map_or(0, |idx| idx + 1);
+        let mut bytes = Vec::with_capacity(split);
+        for &word in &words[..split] {
+            if !(0..256).contains(&word) {
+                return Err(AsciiError::OutOfRange { value: word });
+            }
+            bytes.push(word as u8);
+        }
+        Ok(Self {
+            text: String::from_utf8(bytes).map_err(AsciiError::InvalidUtf8)?,
+            answers: words[split..].to_vec(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AsciiError {
+    Computer(ComputerError),
+    InvalidUtf8(FromUtf8Error),
+    /// A value which is not a byte, followed by more text.
+    OutOfRange {
+        value: Int,
+    },
+    /// The program halted before printing the prompt.
+    UnexpectedHalt(AsciiOutput),
+    /// The program asked for input without printing the prompt.
+    MissingPrompt(AsciiOutput),
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsciiError::Computer(e) => write!(f, "{}", e),
+            AsciiError::InvalidUtf8(e) => write!(f, "invalid text output: {}", e),
+            AsciiError::OutOfRange { value } => write!(f, "output {} is not a character", value),
+            AsciiError::UnexpectedHalt(_) => write!(f, "program halted before the prompt"),
+            AsciiError::MissingPrompt(_) => write!(f, "program waits for input without a prompt"),
+        }
+    }
+}
+
+impl std::error::Error for AsciiError {}
+
+impl From<ComputerError> for AsciiError {
+    fn from(e: ComputerError) -> Self {
+        AsciiError::Computer(e)
+    }
+}
+
+/// Wrapper around an `Instant` exchanging lines of text with the program.
+#[derive(Clone)]
+pub struct AsciiMachine {
+    instant: Instant,
+    halted: bool,
+}
+
+impl AsciiMachine {
+    pub fn new(instant: Instant) -> Self {
+        Self {
+            instant,
+            halted: false,
+        }
+    }
+
+    pub fn instant(&self) -> &Instant {
+        &self.instant
+    }
+
+    pub fn instant_mut(&mut self) -> &mut Instant {
+        &mut self.instant
+    }
+
+    pub fn into_inner(self) -> Instant {
+        self.instant
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Queue `line` followed by a newline as input.
+    pub fn send_line(&mut self, line: &str) {
+        for &b in line.as_bytes() {
+            self.instant.push_input(b as Int);
+        }
+        self.instant.push_input(10);
+    }
+
+    /// Queue values as input without any conversion.
+    pub fn send_raw(&mut self, vals: &[Int]) {
+        self.instant.push_inputs(vals);
+    }
+
+    fn run(&mut self) -> Result<StepResult, AsciiError> {
+        loop {
+            match self.instant.step()? {
+                StepResult::Watch(_) => continue,
+                StepResult::Halt => {
+                    self.halted = true;
+                    break Ok(StepResult::Halt);
+                }
+                result => break Ok(result),
+            }
+        }
+    }
+
+    fn take_output(&mut self) -> Result<AsciiOutput, AsciiError> {
+        AsciiOutput::decode(self.instant.output.drain(..).collect())
+    }
+
+    /// Run until the program waits for input or halts.
+    pub fn read(&mut self) -> Result<AsciiOutput, AsciiError> {
+        while !self.halted && self.run()? == StepResult::Output {}
+        self.take_output()
+    }
+
+    /// Run until the program outputs a newline, waits for input or halts.
+    /// Returns `None` if nothing was output.
+    pub fn read_line(&mut self) -> Result<Option<AsciiOutput>, AsciiError> {
+        while !self.halted && self.instant.output.back() != Some(&10) {
+            if self.run()? != StepResult::Output {
+                break;
+            }
+        }
+        if self.instant.output.is_empty() {
+            Ok(None)
+        } else {
+            self.take_output().map(Some)
+        }
+    }
+
+    /// Run until the output ends with `prompt`, dropping the newline which
+    /// directly follows it if any.
+    pub fn read_until_prompt(&mut self, prompt: &str) -> Result<AsciiOutput, AsciiError> {
+        let prompt: Vec<Int> = prompt.bytes().map(|b| b as Int).collect();
+        loop {
+            if self.halted {
+                return Err(AsciiError::UnexpectedHalt(self.take_output()?));
+            }
+            match self.run()? {
+                StepResult::Output => {
+                    let len = self.instant.output.len();
+                    if len >= prompt.len()
+                        && self
+                            .instant
+                            .output
+                            .range(len - prompt.len()..)
+                            .eq(prompt.iter())
+                    {
+                        break;
+                    }
+                }
+                StepResult::WaitInput => {
+                    return Err(AsciiError::MissingPrompt(self.take_output()?))
+                }
+                StepResult::Halt | StepResult::Watch(_) => {}
+            }
+        }
+        let out = self.take_output()?;
+        if !self.halted && self.run()? == StepResult::Output && self.instant.output[0] != 10 {
+            // not a newline, leave it for the next read
+            return Ok(out);
+        }
+        self.instant.output.clear();
+        Ok(out)
+    }
+
+    /// Run until the program halts.
+    pub fn read_to_end(&mut self) -> Result<AsciiOutput, AsciiError> {
+        while !self.halted {
+            if self.run()? == StepResult::WaitInput {
+                return Err(self.instant.input_exhausted().into());
+            }
+        }
+        self.take_output()
+    }
+}
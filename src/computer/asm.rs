@@ -0,0 +1,231 @@
+//! Assembler for Intcode programs
+//!
+//! Accepts the listing produced by the disassembler as well as hand-written
+//! sources with symbolic labels and `.data` directives:
+//!
+//! ```text
+//! loop:   in [x]
+//!         out [x]
+//!         jt #1, #loop
+//! x:      .data 0
+//! ```
+
+use super::{Int, OpCode, ParameterMode, Program};
+use crate::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone)]
+enum Term {
+    Num(Int),
+    Label(String),
+}
+
+#[derive(Debug, Clone)]
+struct Expr(Vec<(Int, Term)>);
+
+#[derive(Debug)]
+enum ItemKind {
+    Op(OpCode, Vec<Expr>),
+    Data(Vec<Expr>),
+}
+
+#[derive(Debug)]
+struct Item {
+    line: usize,
+    kind: ItemKind,
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+fn parse_expr(input: &str) -> Result<Expr> {
+    let input: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    if input.is_empty() {
+        bail!("missing value");
+    }
+    let mut terms = Vec::new();
+    let mut sign = 1;
+    let mut cur = String::new();
+    let mut push = |sign: Int, cur: &str| -> Result<()> {
+        let term = if let Ok(val) = cur.parse::<Int>() {
+            Term::Num(val)
+        } else if is_label(cur) {
+            Term::Label(cur.to_owned())
+        } else {
+            bail!("invalid value `{}`", cur);
+        };
+        terms.push((sign, term));
+        Ok(())
+    };
+    for c in input.chars() {
+        if (c == '+' || c == '-') && !cur.is_empty() {
+            push(sign, &cur)?;
+            cur.clear();
+            sign = if c == '+' { 1 } else { -1 };
+        } else {
+            cur.push(c);
+        }
+    }
+    push(sign, &cur)?;
+    Ok(Expr(terms))
+}
+
+fn parse_operand(input: &str) -> Result<(ParameterMode, Expr)> {
+    let input = input.trim();
+    let bracketed = |prefix: &str| {
+        input
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(']'))
+    };
+    let (mode, inner) = if let Some(inner) = input.strip_prefix('#') {
+        (ParameterMode::Immediate, inner)
+    } else if let Some(inner) = bracketed("rel[") {
+        (ParameterMode::Relative, inner)
+    } else if let Some(inner) = bracketed("[") {
+        (ParameterMode::Position, inner)
+    } else {
+        bail!(
+            "invalid operand `{}`, expected `[x]`, `#x` or `rel[x]`",
+            input
+        );
+    };
+    Ok((mode, parse_expr(inner)?))
+}
+
+fn parse_opcode(mnemonic: &str) -> Option<OpCode> {
+    (1..=9)
+        .chain(Some(99))
+        .filter_map(|code| OpCode::try_from(code).ok())
+        .find(|op| op.mnemonic().eq_ignore_ascii_case(mnemonic))
+}
+
+fn with_modes(op: OpCode, modes: &[ParameterMode]) -> Result<OpCode> {
+    let word = modes.iter().rev().fold(0, |acc, m| acc * 10 + m.digit()) * 100 + op.code();
+    Ok(OpCode::try_from(word)?)
+}
+
+fn parse_statement(stmt: &str) -> Result<ItemKind> {
+    let (head, rest) = match stmt.find(char::is_whitespace) {
+        Some(idx) => (&stmt[..idx], stmt[idx..].trim()),
+        None => (stmt, ""),
+    };
+    let args: Vec<&str> = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',').collect()
+    };
+    if head.eq_ignore_ascii_case(".data") || head.eq_ignore_ascii_case("data") {
+        if args.is_empty() {
+            bail!("`{}` requires at least one value", head);
+        }
+        let vals = args
+            .iter()
+            .map(|arg| parse_expr(arg))
+            .collect::<Result<Vec<_>>>()?;
+        return Ok(ItemKind::Data(vals));
+    }
+    let op = parse_opcode(head).with_context(|| format!("unknown mnemonic `{}`", head))?;
+    if args.len() != op.size() - 1 {
+        bail!(
+            "`{}` takes {} operand(s), found {}",
+            op.mnemonic(),
+            op.size() - 1,
+            args.len()
+        );
+    }
+    let (modes, exprs): (Vec<_>, Vec<_>) = args
+        .iter()
+        .map(|arg| parse_operand(arg))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    Ok(ItemKind::Op(with_modes(op, &modes)?, exprs))
+}
+
+fn eval(expr: &Expr, labels: &HashMap<String, usize>) -> Result<Int> {
+    let mut val: Int = 0;
+    for (sign, term) in &expr.0 {
+        let term = match term {
+            Term::Num(v) => *v,
+            Term::Label(name) => *labels
+                .get(name)
+                .with_context(|| format!("undefined label `{}`", name))?
+                as Int,
+        };
+        val = sign
+            .checked_mul(term)
+            .and_then(|term| val.checked_add(term))
+            .context("value out of range")?;
+    }
+    Ok(val)
+}
+
+pub fn assemble(src: &str) -> Result<Program> {
+    let mut items: Vec<Item> = Vec::new();
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut addr = 0;
+
+    for (idx, line) in src.lines().enumerate() {
+        let line_no = idx + 1;
+        let mut stmt = match line.find(';') {
+            Some(idx) => &line[..idx],
+            None => line,
+        }
+        .trim();
+        while let Some(idx) = stmt.find(':') {
+            let label = stmt[..idx].trim();
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != addr {
+                    bail!(
+                        "line {}: address mismatch, expected {} but at {}",
+                        line_no,
+                        expected,
+                        addr
+                    );
+                }
+            } else if is_label(label) {
+                if labels.insert(label.to_owned(), addr).is_some() {
+                    bail!("line {}: duplicate label `{}`", line_no, label);
+                }
+            } else {
+                break;
+            }
+            stmt = stmt[idx + 1..].trim();
+        }
+        if stmt.is_empty() {
+            continue;
+        }
+        let kind = parse_statement(stmt).with_context(|| format!("line {}", line_no))?;
+        addr += match &kind {
+            ItemKind::Op(op, _) => op.size(),
+            ItemKind::Data(vals) => vals.len(),
+        };
+        items.push(Item {
+            line: line_no,
+            kind,
+        });
+    }
+
+    let mut code: Vec<Int> = Vec::with_capacity(addr);
+    for item in &items {
+        let exprs = match &item.kind {
+            ItemKind::Op(op, exprs) => {
+                code.push(op.encode());
+                exprs
+            }
+            ItemKind::Data(vals) => vals,
+        };
+        for expr in exprs {
+            code.push(eval(expr, &labels).with_context(|| format!("line {}", item.line))?);
+        }
+    }
+    Ok(Program::new(code))
+}
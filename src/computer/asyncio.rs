@@ -0,0 +1,292 @@
+//! Async adapter running an `Instant` against channels, plus a minimal executor
+
+use super::{BudgetedStep, ComputerResult, InputSource, Instant, Int, OutputSink};
+use futures_core::Stream;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+/// Number of instructions a machine runs before yielding to other tasks.
+const SLICE: usize = 10_000;
+
+#[derive(Debug, Default)]
+struct Shared {
+    queue: VecDeque<Int>,
+    senders: usize,
+    receiver_alive: bool,
+    waker: Option<Waker>,
+}
+
+/// Sending half of an unbounded channel of `Int`.
+#[derive(Debug)]
+pub struct Sender(Arc<Mutex<Shared>>);
+
+/// Receiving half of an unbounded channel of `Int`. The stream ends once the
+/// queue is empty and every `Sender` has been dropped.
+#[derive(Debug)]
+pub struct Receiver(Arc<Mutex<Shared>>);
+
+pub fn channel() -> (Sender, Receiver) {
+    let shared = Arc::new(Mutex::new(Shared {
+        senders: 1,
+        receiver_alive: true,
+        ..Shared::default()
+    }));
+    (Sender(shared.clone()), Receiver(shared))
+}
+
+impl Sender {
+    /// Queue `val`, returning `false` if the receiver is gone.
+    pub fn send(&self, val: Int) -> bool {
+        let mut shared = self.0.lock().unwrap();
+        if !shared.receiver_alive {
+            return false;
+        }
+        shared.queue.push_back(val);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+        true
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Self {
+        self.0.lock().unwrap().senders += 1;
+        Sender(self.0.clone())
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        let mut shared = self.0.lock().unwrap();
+        shared.senders -= 1;
+        if shared.senders == 0 {
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Receiver {
+    /// Take a value without waiting.
+    pub fn try_recv(&self) -> Option<Int> {
+        self.0.lock().unwrap().queue.pop_front()
+    }
+
+    /// Wait for the next value, or `None` once the channel is closed.
+    pub async fn recv(&mut self) -> Option<Int> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl InputSource for Receiver {
+    fn next_input(&mut self) -> Option<Int> {
+        self.try_recv()
+    }
+}
+
+impl OutputSink for Sender {
+    fn send_output(&mut self, val: Int) -> bool {
+        self.send(val)
+    }
+}
+
+impl Stream for Receiver {
+    type Item = Int;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Int>> {
+        let mut shared = self.0.lock().unwrap();
+        if let Some(val) = shared.queue.pop_front() {
+            Poll::Ready(Some(val))
+        } else if shared.senders == 0 {
+            Poll::Ready(None)
+        } else {
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().receiver_alive = false;
+    }
+}
+
+/// Stream of the values output by an `Instant` which reads its input from a
+/// `Receiver`. Yields at `StepResult::WaitInput` until input arrives.
+pub struct OutputStream {
+    instant: Instant,
+    input: Receiver,
+    done: bool,
+}
+
+impl OutputStream {
+    pub fn into_inner(self) -> Instant {
+        self.instant
+    }
+}
+
+impl Stream for OutputStream {
+    type Item = ComputerResult<Int>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(val) = this.instant.pop_output() {
+                return Poll::Ready(Some(Ok(val)));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let mut budget = SLICE;
+            match this.instant.step_with_budget(&mut budget) {
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Ok(BudgetedStep::Halt) => this.done = true,
+                Ok(BudgetedStep::Output) | Ok(BudgetedStep::Watch(_)) => {}
+                Ok(BudgetedStep::BudgetExhausted) => {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Ok(BudgetedStep::WaitInput) => match Pin::new(&mut this.input).poll_next(cx) {
+                    Poll::Ready(Some(val)) => this.instant.push_input(val),
+                    Poll::Ready(None) => {
+                        this.done = true;
+                        return Poll::Ready(Some(Err(this.instant.input_exhausted())));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+            }
+        }
+    }
+}
+
+impl Instant {
+    /// Turn the machine into a stream of its outputs, reading input from
+    /// `input` after anything already queued in `self.input`.
+    pub fn into_stream(self, input: Receiver) -> OutputStream {
+        OutputStream {
+            instant: self,
+            input,
+            done: false,
+        }
+    }
+
+    /// Run the machine to completion, forwarding every output to `output`.
+    pub async fn run_async(self, input: Receiver, output: Sender) -> ComputerResult<Instant> {
+        let mut stream = self.into_stream(input);
+        while let Some(val) = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            output.send(val?);
+        }
+        Ok(stream.into_inner())
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Drive `fut` to completion on the current thread.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = Box::pin(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(val) = fut.as_mut().poll(&mut cx) {
+            break val;
+        }
+        thread::park();
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.lock().unwrap().push_back(self.id);
+    }
+}
+
+/// Handle to the result of a task spawned on an `Executor`.
+pub struct JoinHandle<T>(Rc<RefCell<Option<T>>>);
+
+impl<T> JoinHandle<T> {
+    /// Take the result, if the task has finished.
+    pub fn take(&self) -> Option<T> {
+        self.0.borrow_mut().take()
+    }
+}
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Single threaded executor polling its tasks round-robin whenever they are
+/// woken.
+#[derive(Default)]
+pub struct Executor {
+    tasks: Vec<Option<Task>>,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn<F>(&mut self, fut: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+    {
+        let slot = Rc::new(RefCell::new(None));
+        let result = slot.clone();
+        let id = self.tasks.len();
+        self.tasks.push(Some(Box::pin(async move {
+            *result.borrow_mut() = Some(fut.await);
+        })));
+        self.ready.lock().unwrap().push_back(id);
+        JoinHandle(slot)
+    }
+
+    /// Poll tasks until none of them can make progress. Returns the number of
+    /// tasks left unfinished, which are waiting for something that will never
+    /// happen.
+    pub fn run(&mut self) -> usize {
+        loop {
+            let id = match self.ready.lock().unwrap().pop_front() {
+                Some(id) => id,
+                None => break,
+            };
+            if let Some(task) = &mut self.tasks[id] {
+                let waker = Waker::from(Arc::new(TaskWaker {
+                    id,
+                    ready: self.ready.clone(),
+                }));
+                let mut cx = Context::from_waker(&waker);
+                if task.as_mut().poll(&mut cx).is_ready() {
+                    self.tasks[id] = None;
+                }
+            }
+        }
+        self.tasks.iter().filter(|t| t.is_some()).count()
+    }
+}
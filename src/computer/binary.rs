@@ -0,0 +1,121 @@
+//! Compact binary images of a `Program`
+//!
+//! ```text
+//! magic     b"\0ICB"
+//! version   1 byte
+//! count     varint, number of words
+//! checksum  u32 little endian, FNV-1a of the payload
+//! payload   one zig-zag varint per word
+//! ```
+//!
+//! Varints are LEB128: 7 bits per byte, least significant group first, with
+//! the high bit set on every byte but the last.
+
+use super::{Int, Program};
+use crate::*;
+
+const MAGIC: &[u8] = b"\0ICB";
+const VERSION: u8 = 1;
+
+fn zigzag(val: Int) -> u64 {
+    ((val << 1) ^ (val >> 63)) as u64
+}
+
+fn unzigzag(val: u64) -> Int {
+    (val >> 1) as Int ^ -((val & 1) as Int)
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        buf.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    buf.push(val as u8);
+}
+
+/// Decode a varint from the front of `bytes`, advancing past it.
+fn read_varint(bytes: &mut &[u8]) -> Result<u64> {
+    let mut val = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().context("truncated varint")?;
+        *bytes = rest;
+        let bits = u64::from(byte & 0x7f);
+        if shift == 63 && bits > 1 {
+            bail!("varint overflows 64 bits");
+        }
+        val |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+    bail!("varint overflows 64 bits")
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+impl Program {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.0.len() * 2);
+        for &word in &self.0 {
+            write_varint(&mut payload, zigzag(word));
+        }
+        let mut buf = MAGIC.to_vec();
+        buf.push(VERSION);
+        write_varint(&mut buf, self.0.len() as u64);
+        buf.extend_from_slice(&fnv1a(&payload).to_le_bytes());
+        buf.extend(payload);
+        buf
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self> {
+        let mut bytes = bytes.strip_prefix(MAGIC).context("not an intcode binary")?;
+        let (&version, rest) = bytes.split_first().context("missing version")?;
+        if version != VERSION {
+            bail!("unsupported binary version {}", version);
+        }
+        bytes = rest;
+        let count = read_varint(&mut bytes).context("invalid word count")?;
+        if bytes.len() < 4 {
+            bail!("missing checksum");
+        }
+        let (checksum, payload) = bytes.split_at(4);
+        let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if fnv1a(payload) != checksum {
+            bail!("checksum mismatch");
+        }
+
+        let mut rest = payload;
+        // every word takes at least one byte
+        let mut code = Vec::with_capacity((count as usize).min(payload.len()));
+        for idx in 0..count {
+            let word = read_varint(&mut rest).with_context(|| format!("invalid word {}", idx))?;
+            code.push(unzigzag(word));
+        }
+        if !rest.is_empty() {
+            bail!("{} bytes after the last word", rest.len());
+        }
+        Ok(Program(code))
+    }
+
+    /// The program in the comma separated text format.
+    pub fn to_text(&self) -> String {
+        self.0.iter().join(",")
+    }
+
+    /// Load a file in `data/`, in either the text or the binary format.
+    pub fn load(file_name: &str) -> Result<Self> {
+        let mut bytes = Vec::new();
+        open_input(file_name)?.read_to_end(&mut bytes)?;
+        let prog = if bytes.starts_with(MAGIC) {
+            Self::from_binary(&bytes)
+        } else {
+            let text = String::from_utf8(bytes).context("neither text nor an intcode binary")?;
+            Self::parse(&text).map_err(Error::from)
+        };
+        prog.with_context(|| format!("failed to load {}", file_name))
+    }
+}
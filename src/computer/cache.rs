@@ -0,0 +1,47 @@
+//! Cache of decoded instructions indexed by address
+
+use super::{Decoded, Int, Word};
+
+/// Instructions decoded from memory as it was when the cache was built. The
+/// cache is shared between clones, so each `Instant` tracks the entries its
+/// own writes invalidated in `StaleEntries`.
+#[derive(Debug)]
+pub(super) struct DecodeCache<W = Int> {
+    entries: Vec<Option<Decoded<W>>>,
+}
+
+impl<W: Word> DecodeCache<W> {
+    pub(super) fn new(entries: Vec<Option<Decoded<W>>>) -> Self {
+        Self { entries }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(super) fn get(&self, pc: usize) -> Option<&Decoded<W>> {
+        self.entries.get(pc)?.as_ref()
+    }
+}
+
+/// Addresses of a `DecodeCache` whose entry no longer matches memory.
+#[derive(Debug, Clone, Default)]
+pub(super) struct StaleEntries(Vec<bool>);
+
+impl StaleEntries {
+    pub(super) fn new(len: usize) -> Self {
+        Self(vec![false; len])
+    }
+
+    pub(super) fn contains(&self, pc: usize) -> bool {
+        self.0.get(pc).copied().unwrap_or(true)
+    }
+
+    /// Mark every instruction whose opcode or parameter words include `addr`.
+    pub(super) fn invalidate(&mut self, addr: usize) {
+        let start = addr.saturating_sub(3);
+        for entry in self.0.iter_mut().take(addr + 1).skip(start) {
+            *entry = true;
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Control flow graph recovery for Intcode programs
+//!
+//! The analysis is static: it follows the program as loaded and knows nothing
+//! about code the program writes at run time.
+
+use super::disasm::{self, Operand, Statement};
+use super::{Int, OpCode, ParameterMode, Program};
+use petgraph::Graph;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// How control leaves a basic block.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Terminator {
+    /// Falls into the block starting at the given address.
+    Fallthrough(usize),
+    Jump(usize),
+    Branch {
+        taken: usize,
+        fallthrough: usize,
+    },
+    /// A jump whose target is only known at run time. `fallthrough` is set
+    /// when the jump is conditional.
+    Indirect {
+        fallthrough: Option<usize>,
+    },
+    /// A jump after storing the address following it, which the callee jumps
+    /// back to. `target` is `None` for calls through a pointer.
+    Call {
+        target: Option<usize>,
+        return_site: usize,
+    },
+    /// An unconditional jump through the relative base, ending a function.
+    Return,
+    Halt,
+    /// Control runs into a word that does not decode as an instruction.
+    Invalid,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Edge {
+    Fallthrough,
+    Jump,
+    Taken,
+    Call,
+    /// From a call to its return site.
+    CallReturn,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// Address right after the last instruction.
+    pub end: usize,
+    pub statements: Vec<Statement>,
+    pub terminator: Terminator,
+}
+
+impl BasicBlock {
+    pub fn successors(&self) -> Vec<(usize, Edge)> {
+        match self.terminator {
+            Terminator::Fallthrough(next) => vec![(next, Edge::Fallthrough)],
+            Terminator::Jump(target) => vec![(target, Edge::Jump)],
+            Terminator::Branch { taken, fallthrough } => {
+                vec![(taken, Edge::Taken), (fallthrough, Edge::Fallthrough)]
+            }
+            Terminator::Indirect { fallthrough } => fallthrough
+                .map(|next| (next, Edge::Fallthrough))
+                .into_iter()
+                .collect(),
+            Terminator::Call {
+                target,
+                return_site,
+            } => target
+                .map(|target| (target, Edge::Call))
+                .into_iter()
+                .chain(Some((return_site, Edge::CallReturn)))
+                .collect(),
+            Terminator::Return | Terminator::Halt | Terminator::Invalid => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Cfg {
+    /// Basic blocks keyed by their start address.
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    /// Entry points of called functions.
+    pub functions: BTreeSet<usize>,
+}
+
+fn immediate(operand: &Operand) -> Option<Int> {
+    match operand.mode {
+        ParameterMode::Immediate => Some(operand.value),
+        _ => None,
+    }
+}
+
+fn address(val: Int) -> Option<usize> {
+    if val >= 0 {
+        Some(val as usize)
+    } else {
+        None
+    }
+}
+
+/// Constant stored by an instruction, if it computes one from immediates.
+pub(super) fn stored_constant(op: OpCode, operands: &[Operand]) -> Option<Int> {
+    match op {
+        OpCode::Add(..) => immediate(&operands[0])?.checked_add(immediate(&operands[1])?),
+        OpCode::Mul(..) => immediate(&operands[0])?.checked_mul(immediate(&operands[1])?),
+        _ => None,
+    }
+}
+
+/// Control flow effect of the jump at `addr`, or `None` if it never jumps.
+fn jump_flow(addr: usize, op: OpCode, operands: &[Operand], stored: &[Int]) -> Option<Terminator> {
+    let jump_if = matches!(op, OpCode::JumpIfTrue(..));
+    let next = addr + op.size();
+    let always = match immediate(&operands[0]) {
+        Some(cond) if (cond != 0) != jump_if => return None,
+        Some(_) => true,
+        None => false,
+    };
+    let target = immediate(&operands[1]).and_then(address);
+    let flow = if !always {
+        match target {
+            Some(taken) => Terminator::Branch {
+                taken,
+                fallthrough: next,
+            },
+            None => Terminator::Indirect {
+                fallthrough: Some(next),
+            },
+        }
+    } else if stored.contains(&(next as Int)) {
+        Terminator::Call {
+            target,
+            return_site: next,
+        }
+    } else if let Some(target) = target {
+        Terminator::Jump(target)
+    } else if operands[1].mode == ParameterMode::Relative {
+        Terminator::Return
+    } else {
+        Terminator::Indirect { fallthrough: None }
+    };
+    Some(flow)
+}
+
+impl Cfg {
+    pub fn build(prog: &Program) -> Self {
+        let code = &prog.0;
+        let mut insts: BTreeMap<usize, Statement> = BTreeMap::new();
+        let mut flows: HashMap<usize, Terminator> = HashMap::new();
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        let mut functions = BTreeSet::new();
+        let mut work = vec![0];
+        leaders.insert(0);
+
+        while let Some(start) = work.pop() {
+            let mut addr = start;
+            // constants stored on the way, candidates for return addresses
+            let mut stored = Vec::new();
+            loop {
+                if insts.contains_key(&addr) {
+                    if addr != start {
+                        // joined code decoded earlier
+                        leaders.insert(addr);
+                    }
+                    break;
+                }
+                let stmt = match disasm::decode_at(code, addr) {
+                    Some(stmt @ Statement::Instruction { .. }) => stmt,
+                    _ => break,
+                };
+                let (op, flow) = match &stmt {
+                    Statement::Instruction { op, operands, .. } => {
+                        let flow = match op {
+                            OpCode::JumpIfTrue(..) | OpCode::JumpIfFalse(..) => {
+                                jump_flow(addr, *op, operands, &stored)
+                            }
+                            OpCode::Halt => Some(Terminator::Halt),
+                            _ => None,
+                        };
+                        stored.extend(stored_constant(*op, operands));
+                        (*op, flow)
+                    }
+                    Statement::Data { .. } => unreachable!(),
+                };
+                insts.insert(addr, stmt);
+                let next = addr + op.size();
+                match flow {
+                    None => addr = next,
+                    Some(flow) => {
+                        let succs = BasicBlock {
+                            start: addr,
+                            end: next,
+                            statements: Vec::new(),
+                            terminator: flow,
+                        }
+                        .successors();
+                        for (succ, edge) in succs {
+                            if edge == Edge::Call {
+                                functions.insert(succ);
+                            }
+                            leaders.insert(succ);
+                            work.push(succ);
+                        }
+                        if next < code.len() {
+                            leaders.insert(next);
+                        }
+                        flows.insert(addr, flow);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            if !insts.contains_key(&start) {
+                blocks.insert(
+                    start,
+                    BasicBlock {
+                        start,
+                        end: start,
+                        statements: Vec::new(),
+                        terminator: Terminator::Invalid,
+                    },
+                );
+                continue;
+            }
+            let mut statements = Vec::new();
+            let mut addr = start;
+            let terminator = loop {
+                let stmt = match insts.get(&addr) {
+                    Some(stmt) => stmt.clone(),
+                    None => break Terminator::Invalid,
+                };
+                let next = addr + stmt.size();
+                statements.push(stmt);
+                if let Some(&flow) = flows.get(&addr) {
+                    addr = next;
+                    break flow;
+                }
+                addr = next;
+                if leaders.contains(&addr) {
+                    break Terminator::Fallthrough(addr);
+                }
+            };
+            blocks.insert(
+                start,
+                BasicBlock {
+                    start,
+                    end: addr,
+                    statements,
+                    terminator,
+                },
+            );
+        }
+        // leaders after a terminator are only reachable if something jumps there
+        let reachable = Cfg::reachable(&blocks);
+        blocks.retain(|start, _| reachable.contains(start));
+
+        Cfg { blocks, functions }
+    }
+
+    fn reachable(blocks: &BTreeMap<usize, BasicBlock>) -> BTreeSet<usize> {
+        let mut seen = BTreeSet::new();
+        let mut work = vec![0];
+        while let Some(start) = work.pop() {
+            if !seen.insert(start) {
+                continue;
+            }
+            if let Some(block) = blocks.get(&start) {
+                work.extend(block.successors().into_iter().map(|(succ, _)| succ));
+            }
+        }
+        seen
+    }
+
+    /// Block containing the instruction at `addr`.
+    pub fn block_at(&self, addr: usize) -> Option<&BasicBlock> {
+        self.blocks
+            .range(..=addr)
+            .next_back()
+            .map(|(_, block)| block)
+            .filter(|block| addr < block.end)
+    }
+
+    pub fn edges(&self) -> Vec<(usize, usize, Edge)> {
+        self.blocks
+            .values()
+            .flat_map(|block| {
+                block
+                    .successors()
+                    .into_iter()
+                    .map(move |(succ, edge)| (block.start, succ, edge))
+            })
+            .collect()
+    }
+
+    /// The graph with one node per block, weighted by its start address.
+    pub fn to_graph(&self) -> Graph<usize, Edge> {
+        let mut graph = Graph::new();
+        let nodes: HashMap<usize, _> = self
+            .blocks
+            .keys()
+            .map(|&start| (start, graph.add_node(start)))
+            .collect();
+        for (from, to, edge) in self.edges() {
+            if let (Some(&a), Some(&b)) = (nodes.get(&from), nodes.get(&to)) {
+                graph.add_edge(a, b, edge);
+            }
+        }
+        graph
+    }
+}
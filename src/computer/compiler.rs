@@ -0,0 +1,837 @@
+//! Compiler from a small structured language to Intcode
+//!
+//! ```text
+//! let calls = 0;
+//!
+//! fn fib(n) {
+//!     calls = calls + 1;
+//!     if n < 2 {
+//!         return n;
+//!     }
+//!     return fib(n - 1) + fib(n - 2);
+//! }
+//!
+//! fn main() {
+//!     let n = input();
+//!     while n > 0 {
+//!         output(fib(n));
+//!         n = n - 1;
+//!     }
+//!     output(calls);
+//! }
+//! ```
+//!
+//! Every value is an `Int`. Supported operators are `+ - * < > <= >= == != !`
+//! and short-circuiting `&& ||`; `input()` and `output(x)` map to the `Input`
+//! and `Output` instructions. Global variables live in static memory, while
+//! parameters and locals live in a stack frame addressed through the relative
+//! base. A frame holds the return address at `rel[0]`, followed by the
+//! parameters, the locals and the temporaries of the function. Return values
+//! are passed through a single static cell.
+
+use super::{asm, Int, Program};
+use crate::*;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(Int),
+    Ident(String),
+    Punct(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Num(v) => write!(f, "{}", v),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Punct(p) => write!(f, "{}", p),
+        }
+    }
+}
+
+const PUNCTS: [&str; 19] = [
+    "<=", ">=", "==", "!=", "&&", "||", "(", ")", "{", "}", ",", ";", "=", "+", "-", "*", "<", ">",
+    "!",
+];
+
+const KEYWORDS: [&str; 10] = [
+    "fn", "let", "if", "else", "while", "return", "break", "continue", "input", "output",
+];
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    for (idx, line) in src.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = match line.find("//") {
+            Some(idx) => &line[..idx],
+            None => line,
+        };
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+            let len = if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let val = rest[..len]
+                    .parse()
+                    .with_context(|| format!("line {}: integer literal out of range", line_no))?;
+                tokens.push((Token::Num(val), line_no));
+                len
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push((Token::Ident(rest[..len].to_owned()), line_no));
+                len
+            } else if let Some(p) = PUNCTS.iter().find(|p| rest.starts_with(*p)) {
+                tokens.push((Token::Punct(p), line_no));
+                p.len()
+            } else {
+                bail!("line {}: unexpected character `{}`", line_no, c);
+            };
+            rest = rest[len..].trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl BinOp {
+    fn from_punct(p: &str) -> Option<(BinOp, u8)> {
+        Some(match p {
+            "||" => (BinOp::Or, 1),
+            "&&" => (BinOp::And, 2),
+            "==" => (BinOp::Eq, 3),
+            "!=" => (BinOp::Ne, 3),
+            "<" => (BinOp::Lt, 4),
+            ">" => (BinOp::Gt, 4),
+            "<=" => (BinOp::Le, 4),
+            ">=" => (BinOp::Ge, 4),
+            "+" => (BinOp::Add, 5),
+            "-" => (BinOp::Sub, 5),
+            "*" => (BinOp::Mul, 6),
+            _ => return None,
+        })
+    }
+
+    /// Evaluate on constants, wrapping around like the machine does.
+    fn fold(self, a: Int, b: Int) -> Int {
+        let flag = |cond: bool| if cond { 1 } else { 0 };
+        match self {
+            BinOp::Add => a.wrapping_add(b),
+            BinOp::Sub => a.wrapping_sub(b),
+            BinOp::Mul => a.wrapping_mul(b),
+            BinOp::Lt => flag(a < b),
+            BinOp::Gt => flag(a > b),
+            BinOp::Le => flag(a <= b),
+            BinOp::Ge => flag(a >= b),
+            BinOp::Eq => flag(a == b),
+            BinOp::Ne => flag(a != b),
+            BinOp::And => flag(a != 0 && b != 0),
+            BinOp::Or => flag(a != 0 || b != 0),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Expr {
+    Num(Int),
+    Var(String, usize),
+    Input,
+    Call(String, Vec<Expr>, usize),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Whether evaluating the expression may modify globals.
+    fn has_call(&self) -> bool {
+        match self {
+            Expr::Num(_) | Expr::Var(..) | Expr::Input => false,
+            Expr::Call(..) => true,
+            Expr::Neg(inner) | Expr::Not(inner) => inner.has_call(),
+            Expr::Binary(_, lhs, rhs) => lhs.has_call() || rhs.has_call(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Block(Vec<Stmt>),
+    Let(String, Option<Expr>),
+    Assign(String, Expr, usize),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Output(Expr),
+    Expr(Expr),
+    Break(usize),
+    Continue(usize),
+}
+
+#[derive(Debug)]
+struct Function {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+    line: usize,
+}
+
+#[derive(Debug, Default)]
+struct Module {
+    globals: Vec<(String, Int, usize)>,
+    functions: Vec<Function>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |t| t.1)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.0)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let line = self.line();
+        let token = self
+            .tokens
+            .get(self.pos)
+            .map(|t| t.0.clone())
+            .with_context(|| format!("line {}: unexpected end of input", line))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn is_punct(&self, p: &str) -> bool {
+        match self.peek() {
+            Some(Token::Punct(punct)) => *punct == p,
+            Some(Token::Ident(name)) => name == p,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, p: &str) -> bool {
+        if self.is_punct(p) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, p: &str) -> Result<()> {
+        if !self.eat(p) {
+            match self.peek() {
+                Some(t) => bail!("line {}: expected `{}`, found `{}`", self.line(), p, t),
+                None => bail!("line {}: expected `{}` at end of input", self.line(), p),
+            }
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        let line = self.line();
+        match self.next()? {
+            Token::Ident(name) if !KEYWORDS.contains(&name.as_str()) => Ok(name),
+            t => bail!("line {}: expected identifier, found `{}`", line, t),
+        }
+    }
+
+    fn module(&mut self) -> Result<Module> {
+        let mut module = Module::default();
+        while self.peek().is_some() {
+            let line = self.line();
+            if self.eat("let") {
+                let name = self.ident()?;
+                let val = if self.eat("=") {
+                    let neg = self.eat("-");
+                    match self.next()? {
+                        Token::Num(v) if neg => -v,
+                        Token::Num(v) => v,
+                        t => bail!("line {}: expected integer literal, found `{}`", line, t),
+                    }
+                } else {
+                    0
+                };
+                self.expect(";")?;
+                module.globals.push((name, val, line));
+            } else if self.eat("fn") {
+                let name = self.ident()?;
+                self.expect("(")?;
+                let mut params = Vec::new();
+                while !self.eat(")") {
+                    if !params.is_empty() {
+                        self.expect(",")?;
+                    }
+                    params.push(self.ident()?);
+                }
+                let body = self.block()?;
+                module.functions.push(Function {
+                    name,
+                    params,
+                    body,
+                    line,
+                });
+            } else {
+                bail!("line {}: expected `fn` or `let`", line);
+            }
+        }
+        Ok(module)
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat("}") {
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt> {
+        let line = self.line();
+        let stmt = if self.is_punct("{") {
+            return Ok(Stmt::Block(self.block()?));
+        } else if self.eat("let") {
+            let name = self.ident()?;
+            let val = if self.eat("=") {
+                Some(self.expr(0)?)
+            } else {
+                None
+            };
+            Stmt::Let(name, val)
+        } else if self.eat("if") {
+            return self.if_stmt();
+        } else if self.eat("while") {
+            let cond = self.expr(0)?;
+            return Ok(Stmt::While(cond, self.block()?));
+        } else if self.eat("return") {
+            if self.is_punct(";") {
+                Stmt::Return(None)
+            } else {
+                Stmt::Return(Some(self.expr(0)?))
+            }
+        } else if self.eat("break") {
+            Stmt::Break(line)
+        } else if self.eat("continue") {
+            Stmt::Continue(line)
+        } else if self.eat("output") {
+            self.expect("(")?;
+            let val = self.expr(0)?;
+            self.expect(")")?;
+            Stmt::Output(val)
+        } else if self.tokens.get(self.pos + 1).map(|t| &t.0) == Some(&Token::Punct("=")) {
+            let name = self.ident()?;
+            self.expect("=")?;
+            Stmt::Assign(name, self.expr(0)?, line)
+        } else {
+            Stmt::Expr(self.expr(0)?)
+        };
+        self.expect(";")?;
+        Ok(stmt)
+    }
+
+    fn if_stmt(&mut self) -> Result<Stmt> {
+        let cond = self.expr(0)?;
+        let then = self.block()?;
+        let otherwise = if self.eat("else") {
+            if self.eat("if") {
+                vec![self.if_stmt()?]
+            } else {
+                self.block()?
+            }
+        } else {
+            Vec::new()
+        };
+        Ok(Stmt::If(cond, then, otherwise))
+    }
+
+    fn expr(&mut self, min_prec: u8) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some((op, prec)) = self.binop(min_prec) {
+            self.pos += 1;
+            let rhs = self.expr(prec)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn binop(&self, min_prec: u8) -> Option<(BinOp, u8)> {
+        match self.peek() {
+            Some(Token::Punct(p)) => BinOp::from_punct(p).filter(|&(_, prec)| prec > min_prec),
+            _ => None,
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let line = self.line();
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let e = self.expr(0)?;
+            self.expect(")")?;
+            return Ok(e);
+        }
+        if self.eat("input") {
+            self.expect("(")?;
+            self.expect(")")?;
+            return Ok(Expr::Input);
+        }
+        match self.next()? {
+            Token::Num(v) => Ok(Expr::Num(v)),
+            Token::Ident(ref name) if KEYWORDS.contains(&name.as_str()) => {
+                bail!("line {}: unexpected keyword `{}`", line, name)
+            }
+            Token::Ident(name) => {
+                if self.eat("(") {
+                    let mut args = Vec::new();
+                    while !self.eat(")") {
+                        if !args.is_empty() {
+                            self.expect(",")?;
+                        }
+                        args.push(self.expr(0)?);
+                    }
+                    Ok(Expr::Call(name, args, line))
+                } else {
+                    Ok(Expr::Var(name, line))
+                }
+            }
+            t => bail!("line {}: expected expression, found `{}`", line, t),
+        }
+    }
+}
+
+/// Location of a value during code generation.
+#[derive(Debug, Clone, PartialEq)]
+enum Loc {
+    Imm(Int),
+    Slot(Int),
+    Global(String),
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Loc::Imm(v) => write!(f, "#{}", v),
+            Loc::Slot(slot) => write!(f, "rel[{}]", slot),
+            Loc::Global(name) => write!(f, "[var.{}]", name),
+        }
+    }
+}
+
+struct Codegen {
+    out: String,
+    next_label: usize,
+    funcs: HashMap<String, usize>,
+    globals: HashMap<String, Int>,
+    scopes: Vec<HashMap<String, Int>>,
+    top: Int,
+    loops: Vec<(String, String)>,
+}
+
+impl Codegen {
+    fn emit(&mut self, line: String) {
+        self.out.push_str("        ");
+        self.out.push_str(&line);
+        self.out.push('\n');
+    }
+
+    fn label(&mut self) -> String {
+        self.next_label += 1;
+        format!(".L{}", self.next_label)
+    }
+
+    fn place(&mut self, label: &str) {
+        writeln!(self.out, "{}:", label).unwrap();
+    }
+
+    fn alloc(&mut self) -> Int {
+        self.top += 1;
+        self.top - 1
+    }
+
+    fn copy(&mut self, src: &Loc, dst: &Loc) {
+        if src != dst {
+            self.emit(format!("add {}, #0, {}", src, dst));
+        }
+    }
+
+    fn lookup(&self, name: &str, line: usize) -> Result<Loc> {
+        if let Some(slot) = self.scopes.iter().rev().find_map(|s| s.get(name)) {
+            Ok(Loc::Slot(*slot))
+        } else if self.globals.contains_key(name) {
+            Ok(Loc::Global(name.to_owned()))
+        } else {
+            bail!("line {}: undefined variable `{}`", line, name)
+        }
+    }
+
+    fn function(&mut self, f: &Function) -> Result<()> {
+        self.place(&format!("fn.{}", f.name));
+        let mut scope = HashMap::new();
+        for (i, param) in f.params.iter().enumerate() {
+            if scope.insert(param.clone(), i as Int + 1).is_some() {
+                bail!("line {}: duplicate parameter `{}`", f.line, param);
+            }
+        }
+        self.scopes = vec![scope];
+        self.top = f.params.len() as Int + 1;
+        self.block(&f.body)?;
+        self.emit("add #0, #0, [.ret]".to_owned());
+        self.emit("jt #1, rel[0]".to_owned());
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<()> {
+        let saved = self.top;
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        self.scopes.pop();
+        self.top = saved;
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        let saved = self.top;
+        match stmt {
+            Stmt::Block(stmts) => self.block(stmts)?,
+            Stmt::Let(name, val) => {
+                let src = match val {
+                    Some(e) => self.expr(e)?,
+                    None => Loc::Imm(0),
+                };
+                self.top = saved;
+                let slot = self.alloc();
+                self.copy(&src, &Loc::Slot(slot));
+                self.scopes.last_mut().unwrap().insert(name.clone(), slot);
+                return Ok(());
+            }
+            Stmt::Assign(name, e, line) => {
+                let dst = self.lookup(name, *line)?;
+                let src = self.expr(e)?;
+                self.copy(&src, &dst);
+            }
+            Stmt::If(cond, then, otherwise) => {
+                let else_label = self.label();
+                let end_label = self.label();
+                let c = self.expr(cond)?;
+                self.top = saved;
+                self.emit(format!("jf {}, #{}", c, else_label));
+                self.block(then)?;
+                if !otherwise.is_empty() {
+                    self.emit(format!("jt #1, #{}", end_label));
+                }
+                self.place(&else_label);
+                self.block(otherwise)?;
+                self.place(&end_label);
+            }
+            Stmt::While(cond, body) => {
+                let start_label = self.label();
+                let end_label = self.label();
+                self.place(&start_label);
+                let c = self.expr(cond)?;
+                self.top = saved;
+                self.emit(format!("jf {}, #{}", c, end_label));
+                self.loops.push((start_label.clone(), end_label.clone()));
+                self.block(body)?;
+                self.loops.pop();
+                self.emit(format!("jt #1, #{}", start_label));
+                self.place(&end_label);
+            }
+            Stmt::Return(val) => {
+                let src = match val {
+                    Some(e) => self.expr(e)?,
+                    None => Loc::Imm(0),
+                };
+                self.emit(format!("add {}, #0, [.ret]", src));
+                self.emit("jt #1, rel[0]".to_owned());
+            }
+            Stmt::Output(e) => {
+                let src = self.expr(e)?;
+                self.emit(format!("out {}", src));
+            }
+            Stmt::Expr(e) => {
+                self.expr(e)?;
+            }
+            Stmt::Break(line) | Stmt::Continue(line) => {
+                let (start, end) = self.loops.last().with_context(|| {
+                    format!("line {}: `break` or `continue` outside loop", line)
+                })?;
+                let target = if let Stmt::Break(_) = stmt {
+                    end
+                } else {
+                    start
+                };
+                self.emit(format!("jt #1, #{}", target));
+            }
+        }
+        self.top = saved;
+        Ok(())
+    }
+
+    fn expr(&mut self, e: &Expr) -> Result<Loc> {
+        let saved = self.top;
+        let loc = match e {
+            Expr::Num(v) => return Ok(Loc::Imm(*v)),
+            Expr::Var(name, line) => return self.lookup(name, *line),
+            Expr::Input => {
+                let t = self.alloc();
+                self.emit(format!("in rel[{}]", t));
+                return Ok(Loc::Slot(t));
+            }
+            Expr::Call(name, args, line) => return self.call(name, args, *line),
+            Expr::Neg(inner) => match self.expr(inner)? {
+                Loc::Imm(v) => Loc::Imm(v.wrapping_neg()),
+                a => {
+                    self.top = saved;
+                    let t = self.alloc();
+                    self.emit(format!("mul {}, #-1, rel[{}]", a, t));
+                    Loc::Slot(t)
+                }
+            },
+            Expr::Not(inner) => match self.expr(inner)? {
+                Loc::Imm(v) => Loc::Imm(if v == 0 { 1 } else { 0 }),
+                a => {
+                    self.top = saved;
+                    let t = self.alloc();
+                    self.emit(format!("eq {}, #0, rel[{}]", a, t));
+                    Loc::Slot(t)
+                }
+            },
+            Expr::Binary(op @ BinOp::And, lhs, rhs) | Expr::Binary(op @ BinOp::Or, lhs, rhs) => {
+                self.logical(*op, lhs, rhs)?
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let a = match self.expr(lhs)? {
+                    // read the global before a call in `rhs` can modify it
+                    Loc::Global(name) if rhs.has_call() => {
+                        let t = self.alloc();
+                        self.copy(&Loc::Global(name), &Loc::Slot(t));
+                        Loc::Slot(t)
+                    }
+                    a => a,
+                };
+                let b = self.expr(rhs)?;
+                if let (Loc::Imm(a), Loc::Imm(b)) = (&a, &b) {
+                    self.top = saved;
+                    return Ok(Loc::Imm(op.fold(*a, *b)));
+                }
+                let scratch = self.alloc();
+                self.top = saved;
+                let t = Loc::Slot(self.alloc());
+                match op {
+                    BinOp::Add => self.emit(format!("add {}, {}, {}", a, b, t)),
+                    BinOp::Mul => self.emit(format!("mul {}, {}, {}", a, b, t)),
+                    BinOp::Sub => match b {
+                        Loc::Imm(b) => {
+                            self.emit(format!("add {}, #{}, {}", a, b.wrapping_neg(), t))
+                        }
+                        b => {
+                            self.emit(format!("mul {}, #-1, rel[{}]", b, scratch));
+                            self.emit(format!("add {}, rel[{}], {}", a, scratch, t));
+                        }
+                    },
+                    BinOp::Lt => self.emit(format!("lt {}, {}, {}", a, b, t)),
+                    BinOp::Gt => self.emit(format!("lt {}, {}, {}", b, a, t)),
+                    BinOp::Le => {
+                        self.emit(format!("lt {}, {}, {}", b, a, t));
+                        self.emit(format!("eq {}, #0, {}", t, t));
+                    }
+                    BinOp::Ge => {
+                        self.emit(format!("lt {}, {}, {}", a, b, t));
+                        self.emit(format!("eq {}, #0, {}", t, t));
+                    }
+                    BinOp::Eq => self.emit(format!("eq {}, {}, {}", a, b, t)),
+                    BinOp::Ne => {
+                        self.emit(format!("eq {}, {}, {}", a, b, t));
+                        self.emit(format!("eq {}, #0, {}", t, t));
+                    }
+                    BinOp::And | BinOp::Or => unreachable!(),
+                }
+                t
+            }
+        };
+        Ok(loc)
+    }
+
+    fn logical(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Result<Loc> {
+        let saved = self.top;
+        let short_label = self.label();
+        let end_label = self.label();
+        let a = self.expr(lhs)?;
+        self.top = saved;
+        let t = Loc::Slot(self.alloc());
+        // `t` holds whether `lhs` is zero, which decides the short circuit
+        self.emit(format!("eq {}, #0, {}", a, t));
+        let jump = if op == BinOp::And { "jt" } else { "jf" };
+        self.emit(format!("{} {}, #{}", jump, t, short_label));
+        let b = self.expr(rhs)?;
+        self.emit(format!("eq {}, #0, {}", b, t));
+        self.emit(format!("eq {}, #0, {}", t, t));
+        self.emit(format!("jt #1, #{}", end_label));
+        self.place(&short_label);
+        let short_val = if op == BinOp::And { 0 } else { 1 };
+        self.emit(format!("add #{}, #0, {}", short_val, t));
+        self.place(&end_label);
+        self.top = saved + 1;
+        Ok(t)
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], line: usize) -> Result<Loc> {
+        let arity = *self
+            .funcs
+            .get(name)
+            .with_context(|| format!("line {}: undefined function `{}`", line, name))?;
+        if arity != args.len() {
+            bail!(
+                "line {}: `{}` takes {} argument(s), found {}",
+                line,
+                name,
+                arity,
+                args.len()
+            );
+        }
+        let saved = self.top;
+        let mut vals = Vec::with_capacity(args.len());
+        for arg in args {
+            // globals may be modified by calls in later arguments, so snapshot them
+            let val = match self.expr(arg)? {
+                Loc::Global(name) => {
+                    let t = self.alloc();
+                    self.copy(&Loc::Global(name), &Loc::Slot(t));
+                    Loc::Slot(t)
+                }
+                val => val,
+            };
+            vals.push(val);
+        }
+        let frame = self.top;
+        for (i, val) in vals.iter().enumerate() {
+            self.copy(val, &Loc::Slot(frame + 1 + i as Int));
+        }
+        let ret_label = self.label();
+        self.emit(format!("add #{}, #0, rel[{}]", ret_label, frame));
+        self.emit(format!("arb #{}", frame));
+        self.emit(format!("jt #1, #fn.{}", name));
+        self.place(&ret_label);
+        self.emit(format!("arb #{}", -frame));
+        self.top = saved;
+        let t = self.alloc();
+        self.emit(format!("add [.ret], #0, rel[{}]", t));
+        Ok(Loc::Slot(t))
+    }
+}
+
+/// Compile a source file into assembly accepted by [`asm::assemble`].
+pub fn compile_to_asm(src: &str) -> Result<String> {
+    let tokens = tokenize(src)?;
+    let module = Parser { tokens, pos: 0 }.module()?;
+
+    let mut gen = Codegen {
+        out: String::new(),
+        next_label: 0,
+        funcs: HashMap::new(),
+        globals: HashMap::new(),
+        scopes: Vec::new(),
+        top: 0,
+        loops: Vec::new(),
+    };
+    for f in &module.functions {
+        if gen.funcs.insert(f.name.clone(), f.params.len()).is_some() {
+            bail!("line {}: duplicate function `{}`", f.line, f.name);
+        }
+    }
+    for (name, val, line) in &module.globals {
+        if gen.globals.insert(name.clone(), *val).is_some() {
+            bail!("line {}: duplicate global `{}`", line, name);
+        }
+    }
+    match gen.funcs.get("main") {
+        Some(0) => {}
+        Some(_) => bail!("`main` must not take parameters"),
+        None => bail!("missing `main` function"),
+    }
+
+    gen.emit("arb #.stack".to_owned());
+    gen.emit("add #.halt, #0, rel[0]".to_owned());
+    gen.emit("jt #1, #fn.main".to_owned());
+    gen.place(".halt");
+    gen.emit("hlt".to_owned());
+    for f in &module.functions {
+        gen.function(f)?;
+    }
+    for (name, val, _) in &module.globals {
+        gen.place(&format!("var.{}", name));
+        gen.emit(format!(".data {}", val));
+    }
+    gen.place(".ret");
+    gen.emit(".data 0".to_owned());
+    gen.place(".stack");
+    Ok(gen.out)
+}
+
+pub fn compile(src: &str) -> Result<Program> {
+    asm::assemble(&compile_to_asm(src)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(src: &str) -> Vec<Int> {
+        compile(src).unwrap().start().execute().unwrap()
+    }
+
+    #[test]
+    fn binary_reads_global_before_call() {
+        let src = "
+            let g = 4;
+            fn inc() {
+                g = g + 1;
+                return g;
+            }
+            fn add3(a, b, c) {
+                return a * 100 + b * 10 + c;
+            }
+            fn main() {
+                output(g - inc());
+                output(add3(g, inc(), g));
+                output(inc() - g);
+            }
+        ";
+        assert_eq!(run(src), vec![-1, 566, 0]);
+    }
+}
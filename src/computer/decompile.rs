@@ -0,0 +1,570 @@
+//! Decompiler turning Intcode functions into structured pseudocode
+//!
+//! Functions are found by `Cfg`. Within a function, loops are recovered from
+//! back edges and if/else from post-dominators; whatever does not fit that
+//! shape is left as `goto`.
+
+use super::cfg::{stored_constant, BasicBlock, Cfg, Edge, Terminator};
+use super::disasm::{Operand, Statement};
+use super::{Int, OpCode, ParameterMode, Program};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Virtual node every returning block flows into, for post-dominators.
+const EXIT: usize = usize::MAX;
+
+pub struct Decompiler {
+    cfg: Cfg,
+    names: HashMap<usize, String>,
+    /// Code words some instruction writes to.
+    patched: HashSet<usize>,
+}
+
+pub fn decompile(prog: &Program) -> String {
+    Decompiler::new(prog).decompile()
+}
+
+impl Decompiler {
+    pub fn new(prog: &Program) -> Self {
+        let cfg = Cfg::build(prog);
+        let code: HashSet<usize> = cfg
+            .blocks
+            .values()
+            .flat_map(|block| block.start..block.end)
+            .collect();
+        let patched = cfg
+            .blocks
+            .values()
+            .flat_map(|block| &block.statements)
+            .filter_map(|stmt| match stmt {
+                Statement::Instruction {
+                    op:
+                        OpCode::Add(..)
+                        | OpCode::Mul(..)
+                        | OpCode::Input(..)
+                        | OpCode::LessThan(..)
+                        | OpCode::Equal(..),
+                    operands,
+                    ..
+                } => operands
+                    .last()
+                    .filter(|o| o.mode == ParameterMode::Position),
+                _ => None,
+            })
+            .filter(|o| o.value >= 0 && code.contains(&(o.value as usize)))
+            .map(|o| o.value as usize)
+            .collect();
+        Self {
+            cfg,
+            names: HashMap::new(),
+            patched,
+        }
+    }
+
+    /// Refer to the memory cell, or the function, at `addr` as `name`.
+    pub fn name(&mut self, addr: usize, name: &str) {
+        self.names.insert(addr, name.to_owned());
+    }
+
+    pub fn with_name(mut self, addr: usize, name: &str) -> Self {
+        self.name(addr, name);
+        self
+    }
+
+    pub fn cfg(&self) -> &Cfg {
+        &self.cfg
+    }
+
+    /// Pseudocode of the whole program, starting with the entry point.
+    pub fn decompile(&self) -> String {
+        let mut entries = vec![0];
+        entries.extend(self.cfg.functions.iter().filter(|&&f| f != 0));
+        entries
+            .into_iter()
+            .map(|entry| self.decompile_function(entry))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn decompile_function(&self, entry: usize) -> String {
+        let func = Function::new(&self.cfg, entry);
+        let mut emitter = Emitter {
+            dec: self,
+            func: &func,
+            lines: Vec::new(),
+            emitted: HashSet::new(),
+            gotos: HashSet::new(),
+        };
+        emitter.region(entry, None, None, 1);
+
+        let mut out = format!("fn {}() {{\n", self.function_name(entry));
+        for line in emitter.lines {
+            match line {
+                Line::Label(addr) if emitter.gotos.contains(&addr) => {
+                    out += &format!("L{}:\n", addr);
+                }
+                Line::Label(_) => {}
+                Line::Text(indent, text) => {
+                    out += &"    ".repeat(indent);
+                    out += &text;
+                    out.push('\n');
+                }
+            }
+        }
+        out += "}\n";
+        out
+    }
+
+    fn function_name(&self, addr: usize) -> String {
+        match self.names.get(&addr) {
+            Some(name) => name.clone(),
+            None if addr == 0 => "main".to_owned(),
+            None => format!("fn_{}", addr),
+        }
+    }
+
+    fn cell(&self, addr: Int) -> String {
+        if addr >= 0 {
+            if let Some(name) = self.names.get(&(addr as usize)) {
+                return name.clone();
+            }
+        }
+        format!("m[{}]", addr)
+    }
+
+    /// Operand stored in the word at `at`. Words the program writes to are
+    /// shown as the cell holding them, like `m[m[566]]`.
+    fn operand(&self, at: usize, operand: &Operand) -> String {
+        let value = if self.patched.contains(&at) {
+            self.cell(at as Int)
+        } else {
+            operand.value.to_string()
+        };
+        match operand.mode {
+            ParameterMode::Position if self.patched.contains(&at) => format!("m[{}]", value),
+            ParameterMode::Position => self.cell(operand.value),
+            ParameterMode::Immediate => value,
+            ParameterMode::Relative => format!("rel[{}]", value),
+        }
+    }
+
+    /// Destination written through the operand stored at `at`.
+    fn target(&self, at: usize, operand: &Operand) -> String {
+        match operand.mode {
+            ParameterMode::Immediate => self.cell(at as Int),
+            _ => self.operand(at, operand),
+        }
+    }
+
+    /// Pseudocode for a single instruction which does not transfer control.
+    fn lift(&self, stmt: &Statement) -> Option<String> {
+        let (addr, op, ops) = match stmt {
+            Statement::Instruction { addr, op, operands } => (*addr, *op, operands),
+            Statement::Data { value, .. } => return Some(format!("data {}", value)),
+        };
+        let arg = |i: usize| self.operand(addr + 1 + i, &ops[i]);
+        let dst = |i: usize| self.target(addr + 1 + i, &ops[i]);
+        // constant operand, unless the program rewrites it
+        let imm = |i: usize| match ops[i].mode {
+            ParameterMode::Immediate if !self.patched.contains(&(addr + 1 + i)) => {
+                Some(ops[i].value)
+            }
+            _ => None,
+        };
+        let text = match op {
+            OpCode::Add(..) => {
+                let dst = dst(2);
+                match (imm(0), imm(1)) {
+                    (Some(0), _) => format!("{} = {}", dst, arg(1)),
+                    (_, Some(0)) => format!("{} = {}", dst, arg(0)),
+                    (_, Some(v)) if v < 0 && v.checked_neg().is_some() => {
+                        format!("{} = {} - {}", dst, arg(0), -v)
+                    }
+                    _ => format!("{} = {} + {}", dst, arg(0), arg(1)),
+                }
+            }
+            OpCode::Mul(..) => {
+                let dst = dst(2);
+                match (imm(0), imm(1)) {
+                    (Some(1), _) => format!("{} = {}", dst, arg(1)),
+                    (_, Some(1)) => format!("{} = {}", dst, arg(0)),
+                    (_, Some(-1)) => format!("{} = -{}", dst, arg(0)),
+                    _ => format!("{} = {} * {}", dst, arg(0), arg(1)),
+                }
+            }
+            OpCode::Input(..) => format!("{} = in()", dst(0)),
+            OpCode::Output(..) => format!("out({})", arg(0)),
+            OpCode::LessThan(..) => format!("{} = {} < {}", dst(2), arg(0), arg(1)),
+            OpCode::Equal(..) => format!("{} = {} == {}", dst(2), arg(0), arg(1)),
+            OpCode::AdjustBase(..) => format!("rb += {}", arg(0)),
+            // jumps which never jump
+            OpCode::JumpIfTrue(..) | OpCode::JumpIfFalse(..) => return None,
+            OpCode::Halt => "halt".to_owned(),
+        };
+        Some(text)
+    }
+
+    /// Condition under which the jump ending `block` is taken, and the
+    /// expression of its target.
+    fn jump(&self, block: &BasicBlock) -> (String, String) {
+        match block.statements.last() {
+            Some(Statement::Instruction { addr, op, operands }) => {
+                let cond = self.operand(addr + 1, &operands[0]);
+                let cond = match op {
+                    OpCode::JumpIfTrue(..) => cond,
+                    _ => format!("!{}", cond),
+                };
+                (cond, self.operand(addr + 2, &operands[1]))
+            }
+            _ => ("?".to_owned(), "?".to_owned()),
+        }
+    }
+}
+
+/// Blocks of one function, linked by the edges staying inside it.
+struct Function<'a> {
+    blocks: BTreeMap<usize, &'a BasicBlock>,
+    succs: HashMap<usize, Vec<usize>>,
+    dom: HashMap<usize, BTreeSet<usize>>,
+    pdom: HashMap<usize, BTreeSet<usize>>,
+}
+
+impl<'a> Function<'a> {
+    fn new(cfg: &'a Cfg, entry: usize) -> Self {
+        let mut blocks = BTreeMap::new();
+        let mut succs: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut work = vec![entry];
+        while let Some(start) = work.pop() {
+            if blocks.contains_key(&start) {
+                continue;
+            }
+            let block = match cfg.blocks.get(&start) {
+                Some(block) => block,
+                None => continue,
+            };
+            blocks.insert(start, block);
+            let next: Vec<usize> = block
+                .successors()
+                .into_iter()
+                .filter(|&(_, edge)| edge != Edge::Call)
+                .map(|(succ, _)| succ)
+                .filter(|succ| cfg.blocks.contains_key(succ))
+                .collect();
+            work.extend(&next);
+            succs.insert(start, next);
+        }
+        let mut func = Self {
+            blocks,
+            succs,
+            dom: HashMap::new(),
+            pdom: HashMap::new(),
+        };
+        func.dom = func.dominators(entry);
+        func.pdom = func.post_dominators();
+        func
+    }
+
+    fn preds(&self) -> HashMap<usize, Vec<usize>> {
+        let mut preds: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (&from, succs) in &self.succs {
+            for &to in succs {
+                preds.entry(to).or_default().push(from);
+            }
+        }
+        preds
+    }
+
+    /// Iterative data flow: `dom(n) = {n} + intersection of dom(p)` over the
+    /// predecessors `p` given by `edges`.
+    fn solve(
+        nodes: &[usize],
+        roots: &[usize],
+        edges: &HashMap<usize, Vec<usize>>,
+    ) -> HashMap<usize, BTreeSet<usize>> {
+        let all: BTreeSet<usize> = nodes.iter().chain(roots).copied().collect();
+        let mut sets: HashMap<usize, BTreeSet<usize>> =
+            nodes.iter().map(|&n| (n, all.clone())).collect();
+        for &root in roots {
+            sets.insert(root, Some(root).into_iter().collect());
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &n in nodes {
+                if roots.contains(&n) {
+                    continue;
+                }
+                let mut set: Option<BTreeSet<usize>> = None;
+                for p in edges.get(&n).into_iter().flatten() {
+                    let ps = &sets[p];
+                    set = Some(match set {
+                        None => ps.clone(),
+                        Some(s) => s.intersection(ps).copied().collect(),
+                    });
+                }
+                let mut set = set.unwrap_or_default();
+                set.insert(n);
+                if set != sets[&n] {
+                    sets.insert(n, set);
+                    changed = true;
+                }
+            }
+        }
+        sets
+    }
+
+    fn dominators(&self, entry: usize) -> HashMap<usize, BTreeSet<usize>> {
+        let nodes: Vec<usize> = self.blocks.keys().copied().collect();
+        Self::solve(&nodes, &[entry], &self.preds())
+    }
+
+    fn post_dominators(&self) -> HashMap<usize, BTreeSet<usize>> {
+        let mut nodes: Vec<usize> = self.blocks.keys().copied().collect();
+        let mut edges = self.succs.clone();
+        for succs in edges.values_mut() {
+            if succs.is_empty() {
+                succs.push(EXIT);
+            }
+        }
+        nodes.push(EXIT);
+        Self::solve(&nodes, &[EXIT], &edges)
+    }
+
+    /// Closest strict post-dominator of `n`, if it is a real block.
+    fn merge_point(&self, n: usize) -> Option<usize> {
+        let pdom = &self.pdom[&n];
+        pdom.iter()
+            .copied()
+            .filter(|&d| d != n && d != EXIT)
+            .find(|d| self.pdom[d].len() + 1 == pdom.len())
+    }
+
+    /// Blocks with a back edge into `header`.
+    fn latches(&self, header: usize) -> Vec<usize> {
+        self.succs
+            .iter()
+            .filter(|(from, succs)| succs.contains(&header) && self.dom[from].contains(&header))
+            .map(|(&from, _)| from)
+            .collect()
+    }
+
+    fn loop_body(&self, header: usize, latches: &[usize]) -> BTreeSet<usize> {
+        let preds = self.preds();
+        let mut body: BTreeSet<usize> = Some(header).into_iter().collect();
+        let mut work = latches.to_vec();
+        while let Some(n) = work.pop() {
+            if body.insert(n) {
+                work.extend(preds.get(&n).into_iter().flatten());
+            }
+        }
+        body
+    }
+
+    fn loop_exit(&self, header: usize, body: &BTreeSet<usize>) -> Option<usize> {
+        let exits: BTreeSet<usize> = body
+            .iter()
+            .flat_map(|n| self.succs[n].iter().copied())
+            .filter(|n| !body.contains(n))
+            .collect();
+        match self.merge_point(header) {
+            Some(m) if exits.contains(&m) => Some(m),
+            _ => exits.iter().next().copied(),
+        }
+    }
+}
+
+enum Line {
+    Label(usize),
+    Text(usize, String),
+}
+
+struct LoopCtx {
+    header: usize,
+    exit: Option<usize>,
+}
+
+struct Emitter<'a> {
+    dec: &'a Decompiler,
+    func: &'a Function<'a>,
+    lines: Vec<Line>,
+    emitted: HashSet<usize>,
+    gotos: HashSet<usize>,
+}
+
+impl<'a> Emitter<'a> {
+    fn text(&mut self, indent: usize, text: String) {
+        self.lines.push(Line::Text(indent, text));
+    }
+
+    /// Emit the code starting at `node` until it reaches `stop`.
+    fn region(
+        &mut self,
+        mut node: usize,
+        stop: Option<usize>,
+        lp: Option<&LoopCtx>,
+        indent: usize,
+    ) {
+        loop {
+            if Some(node) == stop {
+                return;
+            }
+            if let Some(lp) = lp {
+                if node == lp.header {
+                    self.text(indent, "continue".to_owned());
+                    return;
+                }
+                if Some(node) == lp.exit {
+                    self.text(indent, "break".to_owned());
+                    return;
+                }
+            }
+            if self.emitted.contains(&node) || !self.func.blocks.contains_key(&node) {
+                self.gotos.insert(node);
+                self.text(indent, format!("goto L{}", node));
+                return;
+            }
+            let latches = self.func.latches(node);
+            let next = if latches.is_empty() {
+                self.block(node, lp, indent)
+            } else {
+                let body = self.func.loop_body(node, &latches);
+                let inner = LoopCtx {
+                    header: node,
+                    exit: self.func.loop_exit(node, &body),
+                };
+                self.text(indent, "loop {".to_owned());
+                if let Some(next) = self.block(node, Some(&inner), indent + 1) {
+                    self.region(next, None, Some(&inner), indent + 1);
+                }
+                self.text(indent, "}".to_owned());
+                inner.exit
+            };
+            match next {
+                Some(next) => node = next,
+                None => return,
+            }
+        }
+    }
+
+    /// Emit a single block, returning where control continues afterwards.
+    fn block(&mut self, node: usize, lp: Option<&LoopCtx>, indent: usize) -> Option<usize> {
+        self.emitted.insert(node);
+        self.lines.push(Line::Label(node));
+        let block = self.func.blocks[&node];
+        let mut body: Vec<&Statement> = block.statements.iter().collect();
+        match block.terminator {
+            Terminator::Branch { .. }
+            | Terminator::Indirect { .. }
+            | Terminator::Jump(_)
+            | Terminator::Call { .. }
+            | Terminator::Return
+            | Terminator::Halt => {
+                body.pop();
+            }
+            _ => {}
+        }
+        if let Terminator::Call { return_site, .. } = block.terminator {
+            // drop storing the return address
+            if let Some(pos) = body.iter().rposition(|stmt| match stmt {
+                Statement::Instruction { op, operands, .. } => {
+                    stored_constant(*op, operands) == Some(return_site as Int)
+                }
+                _ => false,
+            }) {
+                body.remove(pos);
+            }
+        }
+        for stmt in body {
+            if let Some(text) = self.dec.lift(stmt) {
+                self.text(indent, text);
+            }
+        }
+
+        match block.terminator {
+            Terminator::Fallthrough(next) | Terminator::Jump(next) => Some(next),
+            Terminator::Call {
+                target,
+                return_site,
+            } => {
+                // the target operand is the last word of the block
+                let patched = self.dec.patched.contains(&(block.end - 1));
+                let callee = match target {
+                    Some(target) if !patched => self.dec.function_name(target),
+                    _ => format!("*{}", self.dec.jump(block).1),
+                };
+                self.text(indent, format!("{}()", callee));
+                Some(return_site)
+            }
+            Terminator::Return => {
+                self.text(indent, "return".to_owned());
+                None
+            }
+            Terminator::Halt => {
+                self.text(indent, "halt".to_owned());
+                None
+            }
+            Terminator::Invalid => {
+                self.text(indent, format!("invalid code at {}", block.end));
+                None
+            }
+            Terminator::Indirect { fallthrough } => {
+                let (cond, target) = self.dec.jump(block);
+                match fallthrough {
+                    Some(next) => {
+                        self.text(indent, format!("if ({}) goto *{}", cond, target));
+                        Some(next)
+                    }
+                    None => {
+                        self.text(indent, format!("goto *{}", target));
+                        None
+                    }
+                }
+            }
+            Terminator::Branch { taken, fallthrough } => {
+                let (cond, _) = self.dec.jump(block);
+                let merge = self.func.merge_point(node);
+                let (cond, then, other) = if Some(fallthrough) == merge {
+                    (cond, taken, fallthrough)
+                } else {
+                    (negate(&cond), fallthrough, taken)
+                };
+                let head = self.lines.len();
+                self.text(indent, format!("if ({}) {{", cond));
+                self.region(then, merge, lp, indent + 1);
+                if Some(other) != merge {
+                    let empty = self.lines[head + 1..]
+                        .iter()
+                        .all(|line| matches!(line, Line::Label(_)));
+                    if empty {
+                        // only jumps on the way to the merge point
+                        self.lines[head] = Line::Text(indent, format!("if ({}) {{", negate(&cond)));
+                    } else {
+                        self.text(indent, "} else {".to_owned());
+                    }
+                    self.region(other, merge, lp, indent + 1);
+                }
+                self.text(indent, "}".to_owned());
+                merge
+            }
+        }
+    }
+}
+
+fn negate(cond: &str) -> String {
+    match cond.strip_prefix('!') {
+        Some(cond) => cond.to_owned(),
+        None => format!("!{}", cond),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patched_operand_is_not_simplified() {
+        // the zero added at address 1 is overwritten with 7 before the next pass
+        let prog = Program::new(vec![1101, 0, 5, 20, 1101, 7, 0, 1, 1105, 1, 0]);
+        let text = decompile(&prog);
+        assert!(text.contains("m[20] = m[1] + 5"), "{}", text);
+    }
+}
@@ -0,0 +1,97 @@
+//! Disassembler for Intcode programs
+
+use super::{Int, OpCode, ParameterMode, Program};
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Operand {
+    pub mode: ParameterMode,
+    pub value: Int,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            ParameterMode::Position => write!(f, "[{}]", self.value),
+            ParameterMode::Immediate => write!(f, "#{}", self.value),
+            ParameterMode::Relative => write!(f, "rel[{}]", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Statement {
+    Instruction {
+        addr: usize,
+        op: OpCode,
+        operands: Vec<Operand>,
+    },
+    Data {
+        addr: usize,
+        value: Int,
+    },
+}
+
+impl Statement {
+    pub fn addr(&self) -> usize {
+        match self {
+            Statement::Instruction { addr, .. } | Statement::Data { addr, .. } => *addr,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { op, .. } => op.size(),
+            Statement::Data { .. } => 1,
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Instruction { op, operands, .. } => {
+                write!(f, "{}", op.mnemonic())?;
+                for (i, operand) in operands.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{}{}", sep, operand)?;
+                }
+                Ok(())
+            }
+            Statement::Data { value, .. } => write!(f, "DATA {}", value),
+        }
+    }
+}
+
+/// Decode the instruction at `addr`, if the word there is a valid instruction
+/// whose encoding would survive a round trip and whose operands are in bounds.
+pub fn decode_at(code: &[Int], addr: usize) -> Option<Statement> {
+    let word = *code.get(addr)?;
+    let op = OpCode::try_from(word).ok()?;
+    if op.encode() != word || addr + op.size() > code.len() {
+        return None;
+    }
+    let operands = op
+        .modes()
+        .into_iter()
+        .zip(&code[addr + 1..addr + op.size()])
+        .map(|(mode, &value)| Operand { mode, value })
+        .collect();
+    Some(Statement::Instruction { addr, op, operands })
+}
+
+pub fn disassemble(prog: &Program) -> Vec<Statement> {
+    let code = &prog.0;
+    let mut out = Vec::new();
+    let mut addr = 0;
+    while addr < code.len() {
+        let stmt = decode_at(code, addr).unwrap_or(Statement::Data {
+            addr,
+            value: code[addr],
+        });
+        addr += stmt.size();
+        out.push(stmt);
+    }
+    out
+}
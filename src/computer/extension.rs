@@ -0,0 +1,214 @@
+//! Custom opcodes registered on an `Instant`
+//!
+//! Words that decode as a built-in instruction never reach the registry, so
+//! programs without custom opcodes run exactly as before.
+
+use super::{
+    ComputerError, ComputerResult, Decoded, Instant, Int, ParameterMode, StepResult, Word,
+};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+pub type Handler<W> =
+    dyn Fn(&mut Instant<W>, &Params<W>) -> ComputerResult<Option<StepResult<W>>> + Send + Sync;
+
+#[derive(Clone)]
+pub(super) struct Extension<W: Word> {
+    name: &'static str,
+    params: usize,
+    handler: Arc<Handler<W>>,
+}
+
+/// Parameters of a custom instruction, as stored after its opcode.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Params<W = Int> {
+    /// Address of the instruction.
+    pub pc: usize,
+    pub modes: Vec<ParameterMode>,
+    pub args: Vec<W>,
+}
+
+impl<W: Word> Params<W> {
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Value of parameter `idx`, counting from 0, resolved like a built-in
+    /// instruction would.
+    pub fn read(&self, instant: &mut Instant<W>, idx: usize) -> ComputerResult<W> {
+        let val = instant.read_arg(&self.args[idx], self.modes[idx])?;
+        if instant.instrumented {
+            if let Some(addr) = instant.arg_address(&self.args[idx], self.modes[idx]) {
+                instant.record_read(addr);
+            }
+        }
+        Ok(val)
+    }
+
+    /// Store `val` where parameter `idx`, counting from 0, points to.
+    pub fn write(&self, instant: &mut Instant<W>, idx: usize, val: W) -> ComputerResult<()> {
+        instant.write_arg(&self.args[idx], self.pc + idx + 1, self.modes[idx], val)
+    }
+}
+
+/// An instruction ready to execute.
+pub(super) enum Fetched<W: Word> {
+    Builtin(Decoded<W>),
+    Extension(Extension<W>, Params<W>),
+}
+
+impl<W: Word> Fetched<W> {
+    pub(super) fn size(&self) -> usize {
+        match self {
+            Fetched::Builtin(ins) => ins.op.size(),
+            Fetched::Extension(_, params) => params.len() + 1,
+        }
+    }
+
+    pub(super) fn mnemonic(&self) -> &'static str {
+        match self {
+            Fetched::Builtin(ins) => ins.op.mnemonic(),
+            Fetched::Extension(ext, _) => ext.name,
+        }
+    }
+
+    pub(super) fn modes(&self) -> Vec<ParameterMode> {
+        match self {
+            Fetched::Builtin(ins) => ins.op.modes(),
+            Fetched::Extension(_, params) => params.modes.clone(),
+        }
+    }
+}
+
+fn is_builtin(code: Int) -> bool {
+    matches!(code, 1..=9 | 99)
+}
+
+impl<W: Word> Instant<W> {
+    /// Execute words with `code` as their last two digits by calling
+    /// `handler` with the `params` parameters following them. Parameter modes
+    /// are taken from the higher digits as usual.
+    ///
+    /// Unless the handler moves `pc` or returns `StepResult::WaitInput` or
+    /// `StepResult::Halt`, execution continues after the parameters.
+    ///
+    /// Panics if `code` is not a two digit opcode or belongs to a built-in
+    /// instruction.
+    pub fn register_opcode<F>(&mut self, code: Int, name: &'static str, params: usize, handler: F)
+    where
+        F: Fn(&mut Instant<W>, &Params<W>) -> ComputerResult<Option<StepResult<W>>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        assert!(
+            (1..100).contains(&code) && !is_builtin(code),
+            "cannot register opcode {}",
+            code
+        );
+        self.extensions.insert(
+            code,
+            Extension {
+                name,
+                params,
+                handler: Arc::new(handler),
+            },
+        );
+    }
+
+    pub fn with_opcode<F>(
+        mut self,
+        code: Int,
+        name: &'static str,
+        params: usize,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(&mut Instant<W>, &Params<W>) -> ComputerResult<Option<StepResult<W>>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.register_opcode(code, name, params, handler);
+        self
+    }
+
+    /// Decode the instruction at `pc` as a custom opcode, after it failed to
+    /// decode as a built-in one with `err`.
+    pub(super) fn fetch_extension(&self, err: ComputerError) -> ComputerResult<Fetched<W>> {
+        let word = match err {
+            ComputerError::InvalidOpCode { word, .. } | ComputerError::InvalidMode { word, .. }
+                if !self.extensions.is_empty() && word >= 0 =>
+            {
+                word
+            }
+            _ => return Err(err),
+        };
+        let ext = match self.extensions.get(&(word % 100)) {
+            Some(ext) => ext.clone(),
+            None => return Err(err),
+        };
+        let mut modes = Vec::with_capacity(ext.params);
+        let mut digits = word / 100;
+        for _ in 0..ext.params {
+            let mode =
+                ParameterMode::try_from(digits % 10).map_err(|_| ComputerError::InvalidMode {
+                    pc: self.pc,
+                    word,
+                    base: self.base.saturate(),
+                    mode: digits % 10,
+                })?;
+            modes.push(mode);
+            digits /= 10;
+        }
+        let args = (0..ext.params)
+            .map(|idx| self.read(self.pc + idx + 1))
+            .collect();
+        let params = Params {
+            pc: self.pc,
+            modes,
+            args,
+        };
+        Ok(Fetched::Extension(ext, params))
+    }
+
+    pub(super) fn exec_extension(
+        &mut self,
+        ext: &Extension<W>,
+        params: &Params<W>,
+    ) -> ComputerResult<Option<StepResult<W>>> {
+        let result = (ext.handler)(self, params)?;
+        match result {
+            Some(StepResult::WaitInput) | Some(StepResult::Halt) => {}
+            _ if self.pc == params.pc => self.pc += params.len() + 1,
+            _ => {}
+        }
+        Ok(result)
+    }
+
+    /// `exec_extension` reporting the input the handler consumed and the
+    /// output it produced to the undo log and loop detector, as they do not
+    /// pass through the instruction helpers.
+    pub(super) fn exec_extension_logged(
+        &mut self,
+        ext: &Extension<W>,
+        params: &Params<W>,
+    ) -> ComputerResult<Option<StepResult<W>>> {
+        let input: Vec<W> = self.input.iter().cloned().collect();
+        let output_len = self.output.len();
+        let result = self.exec_extension(ext, params);
+        let consumed = input.len().saturating_sub(self.input.len());
+        for val in input.into_iter().take(consumed) {
+            self.record_input(val);
+        }
+        let produced: Vec<W> = self.output.iter().skip(output_len).cloned().collect();
+        for val in produced {
+            self.record_output(val);
+        }
+        result
+    }
+}
@@ -0,0 +1,237 @@
+//! Memory backends for `Instant`
+
+use super::{Int, Word};
+use crate::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub trait Memory<W: Word = Int>: fmt::Debug + Send {
+    fn read(&self, addr: usize) -> W;
+
+    fn write(&mut self, addr: usize, val: W);
+
+    /// One past the highest address that has been loaded or written.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn kind(&self) -> MemoryKind;
+
+    /// All non-zero words in ascending address order.
+    fn cells(&self) -> Vec<(usize, W)>;
+
+    fn box_clone(&self) -> Box<dyn Memory<W>>;
+}
+
+impl<W: Word> Clone for Box<dyn Memory<W>> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum MemoryKind {
+    #[default]
+    Dense,
+    Sparse,
+    Paged,
+}
+
+impl MemoryKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryKind::Dense => "dense",
+            MemoryKind::Sparse => "sparse",
+            MemoryKind::Paged => "paged",
+        }
+    }
+
+    pub fn create<W: Word>(self, code: &[W]) -> Box<dyn Memory<W>> {
+        match self {
+            MemoryKind::Dense => Box::new(DenseMemory(code.to_vec())),
+            MemoryKind::Sparse => Box::new(SparseMemory::from(code)),
+            MemoryKind::Paged => Box::new(PagedMemory::from(code)),
+        }
+    }
+}
+
+impl FromStr for MemoryKind {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "dense" => Ok(MemoryKind::Dense),
+            "sparse" => Ok(MemoryKind::Sparse),
+            "paged" => Ok(MemoryKind::Paged),
+            _ => bail!("invalid memory kind `{}`", name),
+        }
+    }
+}
+
+/// Contiguous memory, resized on every out-of-range write.
+#[derive(Debug, Clone, Default)]
+pub struct DenseMemory<W = Int>(pub Vec<W>);
+
+impl<W: Word> Memory<W> for DenseMemory<W> {
+    fn read(&self, addr: usize) -> W {
+        self.0.get(addr).cloned().unwrap_or_default()
+    }
+
+    fn write(&mut self, addr: usize, val: W) {
+        if addr >= self.0.len() {
+            self.0.resize(addr + 1, W::default());
+        }
+        self.0[addr] = val;
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Dense
+    }
+
+    fn cells(&self) -> Vec<(usize, W)> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, val)| !val.is_zero())
+            .map(|(addr, val)| (addr, val.clone()))
+            .collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory<W>> {
+        Box::new(self.clone())
+    }
+}
+
+/// Memory storing each touched word in a hash map.
+#[derive(Debug, Clone, Default)]
+pub struct SparseMemory<W = Int> {
+    cells: HashMap<usize, W>,
+    len: usize,
+}
+
+impl<W: Word> From<&[W]> for SparseMemory<W> {
+    fn from(code: &[W]) -> Self {
+        Self {
+            cells: code
+                .iter()
+                .enumerate()
+                .filter(|(_, val)| !val.is_zero())
+                .map(|(addr, val)| (addr, val.clone()))
+                .collect(),
+            len: code.len(),
+        }
+    }
+}
+
+impl<W: Word> Memory<W> for SparseMemory<W> {
+    fn read(&self, addr: usize) -> W {
+        self.cells.get(&addr).cloned().unwrap_or_default()
+    }
+
+    fn write(&mut self, addr: usize, val: W) {
+        self.len = self.len.max(addr + 1);
+        self.cells.insert(addr, val);
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Sparse
+    }
+
+    fn cells(&self) -> Vec<(usize, W)> {
+        let mut cells: Vec<(usize, W)> = self
+            .cells
+            .iter()
+            .filter(|(_, val)| !val.is_zero())
+            .map(|(&addr, val)| (addr, val.clone()))
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory<W>> {
+        Box::new(self.clone())
+    }
+}
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// Memory split into pages of `PAGE_SIZE` words, allocated on first write.
+///
+/// Pages are shared between clones and copied on write, so cloning an
+/// `Instant` only copies the pages it later modifies.
+#[derive(Debug, Clone, Default)]
+pub struct PagedMemory<W = Int> {
+    pages: HashMap<usize, Arc<Vec<W>>>,
+    len: usize,
+}
+
+impl<W: Word> From<&[W]> for PagedMemory<W> {
+    fn from(code: &[W]) -> Self {
+        let mut pages = HashMap::new();
+        for (idx, chunk) in code.chunks(PAGE_SIZE).enumerate() {
+            let mut page = chunk.to_vec();
+            page.resize(PAGE_SIZE, W::default());
+            pages.insert(idx, Arc::new(page));
+        }
+        Self {
+            pages,
+            len: code.len(),
+        }
+    }
+}
+
+impl<W: Word> Memory<W> for PagedMemory<W> {
+    fn read(&self, addr: usize) -> W {
+        match self.pages.get(&(addr / PAGE_SIZE)) {
+            Some(page) => page[addr % PAGE_SIZE].clone(),
+            None => W::default(),
+        }
+    }
+
+    fn write(&mut self, addr: usize, val: W) {
+        self.len = self.len.max(addr + 1);
+        let page = self
+            .pages
+            .entry(addr / PAGE_SIZE)
+            .or_insert_with(|| Arc::new(vec![W::default(); PAGE_SIZE]));
+        Arc::make_mut(page)[addr % PAGE_SIZE] = val;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Paged
+    }
+
+    fn cells(&self) -> Vec<(usize, W)> {
+        let mut idxs: Vec<usize> = self.pages.keys().copied().collect();
+        idxs.sort();
+        idxs.into_iter()
+            .flat_map(|idx| {
+                self.pages[&idx]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, val)| !val.is_zero())
+                    .map(move |(off, val)| (idx * PAGE_SIZE + off, val.clone()))
+            })
+            .collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory<W>> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,281 @@
+//! Multi-threaded runtime for networks of `Instant`s exchanging packets
+
+use super::{BudgetedStep, ComputerError, Instant, Int};
+use crate::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// Instructions a machine executes between checks whether the network stopped.
+const STOP_CHECK_INTERVAL: usize = 10_000;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+/// Decides where the packets output by the machines of a `Network` go.
+pub trait Router {
+    /// Number of consecutive output words forming one packet.
+    fn packet_size(&self) -> usize;
+
+    /// Handle a packet output by machine `src`.
+    fn route(&mut self, outbox: &mut Outbox, src: usize, packet: &[Int]) -> Result<Control>;
+
+    /// Called once every machine is blocked on input with nothing in flight.
+    /// Stopping is the only sensible choice unless something gets sent.
+    fn idle(&mut self, _outbox: &mut Outbox) -> Result<Control> {
+        Ok(Control::Stop)
+    }
+}
+
+/// Input queues of the machines of a running `Network`.
+pub struct Outbox {
+    inputs: Vec<mpsc::Sender<Vec<Int>>>,
+    delivered: Vec<usize>,
+}
+
+impl Outbox {
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Queue `words` as input of machine `dst`. The machine sees all of them
+    /// at once, so it never observes half of a packet.
+    pub fn send(&mut self, dst: usize, words: &[Int]) -> Result<()> {
+        let input = self
+            .inputs
+            .get(dst)
+            .with_context(|| format!("no machine with id {}", dst))?;
+        // a halted machine silently drops its input
+        if input.send(words.to_vec()).is_ok() {
+            self.delivered[dst] += 1;
+        }
+        Ok(())
+    }
+}
+
+enum Event {
+    Packet(usize, Vec<Int>),
+    /// The machine is blocked on input after receiving this many messages.
+    Idle(usize, usize),
+    Halt(usize),
+    Error(usize, ComputerError),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum State {
+    Running,
+    Idle(usize),
+    Halted,
+}
+
+struct Worker {
+    id: usize,
+    instant: Instant,
+    input: mpsc::Receiver<Vec<Int>>,
+    events: mpsc::Sender<Event>,
+    packet_size: usize,
+    idle_input: Option<Int>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Worker {
+    fn run(mut self) {
+        let mut received = 0;
+        // whether the machine did anything since it was last given `idle_input`
+        let mut progress = true;
+        let mut budget = STOP_CHECK_INTERVAL;
+        loop {
+            let event = match self.instant.step_with_budget(&mut budget) {
+                Err(e) => Event::Error(self.id, e),
+                Ok(BudgetedStep::Halt) => Event::Halt(self.id),
+                Ok(BudgetedStep::Watch(_)) => continue,
+                Ok(BudgetedStep::BudgetExhausted) => {
+                    // a machine computing without I/O never notices the
+                    // closed channels
+                    if self.stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    budget = STOP_CHECK_INTERVAL;
+                    continue;
+                }
+                Ok(BudgetedStep::Output) => {
+                    progress = true;
+                    if self.instant.output.len() < self.packet_size {
+                        continue;
+                    }
+                    let packet = self.instant.output.drain(..).collect();
+                    Event::Packet(self.id, packet)
+                }
+                Ok(BudgetedStep::WaitInput) => {
+                    match self.input.try_recv() {
+                        Ok(words) => {
+                            received += 1;
+                            progress = true;
+                            self.instant.push_inputs(&words);
+                            continue;
+                        }
+                        Err(TryRecvError::Disconnected) => return,
+                        Err(TryRecvError::Empty) => {}
+                    }
+                    if let (Some(val), true) = (self.idle_input, progress) {
+                        progress = false;
+                        self.instant.push_input(val);
+                        continue;
+                    }
+                    if self.events.send(Event::Idle(self.id, received)).is_err() {
+                        return;
+                    }
+                    match self.input.recv() {
+                        Ok(words) => {
+                            received += 1;
+                            progress = true;
+                            self.instant.push_inputs(&words);
+                            continue;
+                        }
+                        Err(_) => return,
+                    }
+                }
+            };
+            let stop = !matches!(event, Event::Packet(..));
+            if self.events.send(event).is_err() || stop {
+                return;
+            }
+        }
+    }
+}
+
+/// A set of machines addressed by their index, each running on its own
+/// thread. Packets they output are handed to a `Router` on the calling thread.
+#[derive(Default)]
+pub struct Network {
+    instants: Vec<Instant>,
+    idle_input: Option<Int>,
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a machine, returning its id.
+    pub fn add(&mut self, instant: Instant) -> usize {
+        self.instants.push(instant);
+        self.instants.len() - 1
+    }
+
+    pub fn with_machine(mut self, instant: Instant) -> Self {
+        self.add(instant);
+        self
+    }
+
+    /// Feed `val` to a machine waiting on an empty input queue, once per
+    /// stretch of activity, instead of blocking it right away.
+    pub fn with_idle_input(mut self, val: Int) -> Self {
+        self.idle_input = Some(val);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.instants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instants.is_empty()
+    }
+
+    /// Run every machine until the router stops the network, returning the
+    /// router. Machines still busy computing are stopped before returning.
+    pub fn run<R: Router>(self, mut router: R) -> Result<R> {
+        let packet_size = router.packet_size();
+        if packet_size == 0 {
+            bail!("packet size must be positive");
+        }
+        let (events_tx, events) = mpsc::channel();
+        let mut outbox = Outbox {
+            inputs: Vec::with_capacity(self.instants.len()),
+            delivered: vec![0; self.instants.len()],
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut handles = Vec::with_capacity(self.instants.len());
+        for (id, instant) in self.instants.into_iter().enumerate() {
+            let (input_tx, input) = mpsc::channel();
+            outbox.inputs.push(input_tx);
+            let worker = Worker {
+                id,
+                instant,
+                input,
+                events: events_tx.clone(),
+                packet_size,
+                idle_input: self.idle_input,
+                stop: Arc::clone(&stop),
+            };
+            handles.push(thread::spawn(move || worker.run()));
+        }
+        drop(events_tx);
+
+        let mut states = vec![State::Running; handles.len()];
+        let result = loop {
+            let event = match events.recv() {
+                Ok(event) => event,
+                Err(_) => break Ok(()),
+            };
+            let control = match event {
+                Event::Packet(src, packet) => router.route(&mut outbox, src, &packet),
+                Event::Idle(id, received) => {
+                    states[id] = State::Idle(received);
+                    Ok(Control::Continue)
+                }
+                Event::Halt(id) => {
+                    states[id] = State::Halted;
+                    Ok(Control::Continue)
+                }
+                Event::Error(id, e) => Err(Error::new(e).context(format!("machine {}", id))),
+            };
+            let control = match control {
+                Ok(Control::Continue) if quiescent(&states, &outbox.delivered) => {
+                    let before = outbox.delivered.clone();
+                    match router.idle(&mut outbox) {
+                        // nothing was sent, so nothing will ever happen again
+                        Ok(Control::Continue) if before == outbox.delivered => Ok(Control::Stop),
+                        control => control,
+                    }
+                }
+                control => control,
+            };
+            match control {
+                Ok(Control::Continue) => {}
+                Ok(Control::Stop) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+
+        stop.store(true, Ordering::Relaxed);
+        drop(outbox);
+        drop(events);
+        for handle in handles {
+            if handle.join().is_err() {
+                bail!("network machine panicked");
+            }
+        }
+        result.map(|_| router)
+    }
+}
+
+fn quiescent(states: &[State], delivered: &[usize]) -> bool {
+    states
+        .iter()
+        .zip(delivered)
+        .all(|(state, &delivered)| match *state {
+            State::Running => false,
+            State::Idle(received) => received == delivered,
+            State::Halted => true,
+        })
+}
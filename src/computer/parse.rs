@@ -0,0 +1,78 @@
+//! Strict parser for Intcode program text
+//!
+//! Values are separated by commas and may span several lines. Whitespace,
+//! trailing commas and `#` comments running to the end of a line are ignored.
+
+use super::{Program, Word};
+use std::fmt;
+use std::str::FromStr;
+
+/// A token which is not a valid word, located in the source text.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    /// Address the value would have been loaded at.
+    pub index: usize,
+    /// 1-based line of the token.
+    pub line: usize,
+    /// 1-based column of the token, in characters.
+    pub column: usize,
+    /// The offending token, empty for a missing value between two commas.
+    pub token: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.token.is_empty() {
+            write!(f, "missing value")?;
+        } else {
+            write!(f, "invalid value `{}`", self.token)?;
+        }
+        write!(
+            f,
+            " at index {} (line {}, column {})",
+            self.index, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl<W: Word + FromStr> FromStr for Program<W> {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let mut code = Vec::new();
+        for (line_idx, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let parts: Vec<&str> = line.split(',').collect();
+            let mut offset = 0;
+            for (part_idx, part) in parts.iter().enumerate() {
+                let token = part.trim();
+                let column = line[..offset + part.len() - part.trim_start().len()]
+                    .chars()
+                    .count()
+                    + 1;
+                offset += part.len() + 1;
+                // empty before the first or after the last comma of a line
+                if token.is_empty() && (part_idx == 0 || part_idx == parts.len() - 1) {
+                    continue;
+                }
+                let val = token.parse().map_err(|_| ParseError {
+                    index: code.len(),
+                    line: line_idx + 1,
+                    column,
+                    token: token.to_owned(),
+                })?;
+                code.push(val);
+            }
+        }
+        Ok(Program(code))
+    }
+}
+
+impl Program {
+    /// Parse program text, failing on the first token which is not an `Int`.
+    pub fn parse(src: &str) -> Result<Self, ParseError> {
+        src.parse()
+    }
+}
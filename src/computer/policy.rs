@@ -0,0 +1,59 @@
+//! Execution policy controlling how strictly an `Instant` checks its program
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OverflowMode {
+    /// Wrap around silently.
+    Ignore,
+    /// Wrap around and record a `ComputerError::Overflow` in `Instant::warnings`.
+    Warn,
+    /// Fail with `ComputerError::Overflow`.
+    Reject,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ExecutionPolicy {
+    /// Fail on reads from negative addresses instead of reading them as zero.
+    /// Writes and jumps to negative addresses always fail.
+    pub reject_negative_address: bool,
+    /// Fail on writes through an immediate mode parameter.
+    pub reject_immediate_write: bool,
+    /// Maximum number of words the memory may grow to.
+    pub memory_limit: Option<usize>,
+    pub overflow: OverflowMode,
+}
+
+impl ExecutionPolicy {
+    pub fn lenient() -> Self {
+        Self {
+            reject_negative_address: false,
+            reject_immediate_write: false,
+            memory_limit: None,
+            overflow: OverflowMode::Ignore,
+        }
+    }
+
+    pub fn strict() -> Self {
+        Self {
+            reject_negative_address: true,
+            reject_immediate_write: true,
+            memory_limit: Some(1 << 20),
+            overflow: OverflowMode::Reject,
+        }
+    }
+
+    pub fn with_memory_limit(mut self, limit: usize) -> Self {
+        self.memory_limit = Some(limit);
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: OverflowMode) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
+
+impl Default for ExecutionPolicy {
+    fn default() -> Self {
+        Self::lenient()
+    }
+}
@@ -0,0 +1,103 @@
+//! Pluggable input sources and output sinks for `Instant::run_with`
+//!
+//! Closures work directly: `FnMut() -> W` as a source, `FnMut(W)` as a sink.
+//! Iterators, channels and writers go through `InputIter` and `OutputWriter`.
+
+use super::{ComputerError, ComputerResult, Instant, Int, StepResult, Word};
+use std::io::Write;
+
+pub trait InputSource<W = Int> {
+    /// The next input, or `None` if there is none for now.
+    fn next_input(&mut self) -> Option<W>;
+}
+
+pub trait OutputSink<W = Int> {
+    /// Take a single output. Returns `false` if the sink is closed.
+    fn send_output(&mut self, val: W) -> bool;
+}
+
+impl<W, F: FnMut() -> W> InputSource<W> for F {
+    fn next_input(&mut self) -> Option<W> {
+        Some(self())
+    }
+}
+
+impl<W, F: FnMut(W)> OutputSink<W> for F {
+    fn send_output(&mut self, val: W) -> bool {
+        self(val);
+        true
+    }
+}
+
+/// Input taken from an iterator, such as `queue.drain(..)` or
+/// `receiver.try_iter()`.
+#[derive(Debug, Clone)]
+pub struct InputIter<I>(pub I);
+
+impl<W, I: Iterator<Item = W>> InputSource<W> for InputIter<I> {
+    fn next_input(&mut self) -> Option<W> {
+        self.0.next()
+    }
+}
+
+/// Output written one word per line. Closes on the first I/O error.
+#[derive(Debug)]
+pub struct OutputWriter<T: Write> {
+    writer: T,
+    closed: bool,
+}
+
+impl<T: Write> OutputWriter<T> {
+    pub fn new(writer: T) -> Self {
+        Self {
+            writer,
+            closed: false,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.writer
+    }
+}
+
+impl<W: Word, T: Write> OutputSink<W> for OutputWriter<T> {
+    fn send_output(&mut self, val: W) -> bool {
+        if !self.closed && writeln!(self.writer, "{}", val).is_err() {
+            self.closed = true;
+        }
+        !self.closed
+    }
+}
+
+impl<W: Word> Instant<W> {
+    /// Run until the machine halts, taking input from the queue and then from
+    /// `input`, and passing every output to `output`.
+    ///
+    /// Stops early with `StepResult::WaitInput` once `input` runs dry, or with
+    /// `StepResult::Watch` on a watchpoint hit; calling again resumes.
+    pub fn run_with<I, O>(&mut self, mut input: I, mut output: O) -> ComputerResult<StepResult<W>>
+    where
+        I: InputSource<W>,
+        O: OutputSink<W>,
+    {
+        loop {
+            while let Some(val) = self.output.pop_front() {
+                if !output.send_output(val) {
+                    return Err(ComputerError::OutputClosed {
+                        pc: self.pc,
+                        word: self.read(self.pc).saturate(),
+                        base: self.base.saturate(),
+                    });
+                }
+            }
+            match self.step()? {
+                StepResult::Output => {}
+                StepResult::WaitInput => match input.next_input() {
+                    Some(val) => self.input.push_back(val),
+                    None => return Ok(StepResult::WaitInput),
+                },
+                result => return Ok(result),
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Execution profiler for `Instant`
+
+use super::disasm;
+use super::{Instant, Int, ParameterMode, Program, Word};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Number of entries shown per section of the report.
+const TOP: usize = 20;
+
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    pub instructions: u64,
+    pub pcs: HashMap<usize, u64>,
+    pub opcodes: HashMap<&'static str, u64>,
+    pub modes: HashMap<ParameterMode, u64>,
+    /// Taken jumps, keyed by `(from, to)`.
+    pub jumps: HashMap<(usize, usize), u64>,
+    pub reads: HashMap<usize, u64>,
+    pub writes: HashMap<usize, u64>,
+}
+
+/// A loop closed by a backward jump from `tail` to `head`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct HotLoop {
+    pub head: usize,
+    pub tail: usize,
+    pub iterations: u64,
+    /// Instructions executed between `head` and `tail`, including any
+    /// executed there outside of this loop.
+    pub instructions: u64,
+}
+
+fn sorted<K: Copy + Ord>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut entries: Vec<(K, u64)> = counts.iter().map(|(&k, &v)| (k, v)).collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    entries
+}
+
+impl Profile {
+    pub(super) fn record_instruction(
+        &mut self,
+        pc: usize,
+        mnemonic: &'static str,
+        modes: &[ParameterMode],
+    ) {
+        self.instructions += 1;
+        *self.pcs.entry(pc).or_insert(0) += 1;
+        *self.opcodes.entry(mnemonic).or_insert(0) += 1;
+        for &mode in modes {
+            *self.modes.entry(mode).or_insert(0) += 1;
+        }
+    }
+
+    pub(super) fn record_jump(&mut self, from: usize, to: usize) {
+        *self.jumps.entry((from, to)).or_insert(0) += 1;
+    }
+
+    pub(super) fn record_read(&mut self, addr: usize) {
+        *self.reads.entry(addr).or_insert(0) += 1;
+    }
+
+    pub(super) fn record_write(&mut self, addr: usize) {
+        *self.writes.entry(addr).or_insert(0) += 1;
+    }
+
+    /// Backward jumps, hottest first.
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self
+            .jumps
+            .iter()
+            .filter(|(&(from, to), _)| to <= from)
+            .map(|(&(tail, head), &iterations)| HotLoop {
+                head,
+                tail,
+                iterations,
+                instructions: (head..=tail).filter_map(|pc| self.pcs.get(&pc)).sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then(a.head.cmp(&b.head))
+                .then(a.tail.cmp(&b.tail))
+        });
+        loops
+    }
+
+    /// Disassembly of `prog` with the execution count of every instruction
+    /// in front of it.
+    pub fn annotate(&self, prog: &Program) -> String {
+        disasm::disassemble(prog)
+            .iter()
+            .map(|stmt| {
+                let count = self.pcs.get(&stmt.addr()).copied().unwrap_or(0);
+                format!("{:>10} {:>5}: {}\n", count, stmt.addr(), stmt)
+            })
+            .collect()
+    }
+}
+
+/// Sorted report. Addresses are formatted like `Program::disassemble` does,
+/// so the per address sections can be joined with a listing.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.instructions.max(1) as f64;
+        writeln!(f, "instructions {}", self.instructions)?;
+        writeln!(f, "== pc")?;
+        for (pc, count) in sorted(&self.pcs).into_iter().take(TOP) {
+            let share = 100.0 * count as f64 / total;
+            writeln!(f, "{:>5}: {} ({:.1}%)", pc, count, share)?;
+        }
+        writeln!(f, "== opcode")?;
+        for (op, count) in sorted(&self.opcodes) {
+            writeln!(f, "{:>5}: {}", op, count)?;
+        }
+        writeln!(f, "== mode")?;
+        let modes: HashMap<Int, u64> = self.modes.iter().map(|(m, &c)| (m.digit(), c)).collect();
+        for (mode, count) in sorted(&modes) {
+            writeln!(f, "{:>5}: {}", mode, count)?;
+        }
+        writeln!(f, "== jump")?;
+        for ((from, to), count) in sorted(&self.jumps).into_iter().take(TOP) {
+            writeln!(f, "{:>5}: -> {} {}", from, to, count)?;
+        }
+        writeln!(f, "== loop")?;
+        for l in self.hot_loops().into_iter().take(TOP) {
+            writeln!(
+                f,
+                "{:>5}: ..{} {} iterations, {} instructions",
+                l.head, l.tail, l.iterations, l.instructions
+            )?;
+        }
+        writeln!(f, "== read")?;
+        for (addr, count) in sorted(&self.reads).into_iter().take(TOP) {
+            writeln!(f, "{:>5}: {}", addr, count)?;
+        }
+        writeln!(f, "== write")?;
+        for (addr, count) in sorted(&self.writes).into_iter().take(TOP) {
+            writeln!(f, "{:>5}: {}", addr, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Word> Instant<W> {
+    /// Count executed instructions, jumps and memory accesses from now on.
+    pub fn enable_profiling(&mut self) {
+        if self.profile.is_none() {
+            self.profile = Some(Box::default());
+        }
+        self.update_instrumented();
+    }
+
+    pub fn with_profiling(mut self) -> Self {
+        self.enable_profiling();
+        self
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    /// Stop profiling, returning what was collected.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        let profile = self.profile.take().map(|p| *p);
+        self.update_instrumented();
+        profile
+    }
+}
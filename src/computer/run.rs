@@ -0,0 +1,81 @@
+//! Combinators running an `Instant` until some condition holds
+
+use super::{ComputerResult, Instant, Int, StepResult, WatchHit, Word};
+
+/// Why a `run_until*` call returned.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RunOutcome<W = Int> {
+    /// The condition holds.
+    Matched,
+    Halted,
+    /// The machine needs input before it can go on.
+    WaitingInput,
+    BudgetExhausted,
+    Watch(WatchHit<W>),
+}
+
+impl<W: Word> Instant<W> {
+    /// Run until `pred` holds, checking it before every instruction.
+    pub fn run_until<F>(&mut self, pred: F) -> ComputerResult<RunOutcome<W>>
+    where
+        F: FnMut(&Instant<W>) -> bool,
+    {
+        let mut budget = usize::MAX;
+        self.run_until_budget(&mut budget, pred)
+    }
+
+    /// Like `run_until`, but execute at most `*budget` instructions,
+    /// deducting the ones executed from `budget`.
+    pub fn run_until_budget<F>(
+        &mut self,
+        budget: &mut usize,
+        mut pred: F,
+    ) -> ComputerResult<RunOutcome<W>>
+    where
+        F: FnMut(&Instant<W>) -> bool,
+    {
+        loop {
+            if pred(self) {
+                return Ok(RunOutcome::Matched);
+            }
+            if *budget == 0 {
+                return Ok(RunOutcome::BudgetExhausted);
+            }
+            *budget -= 1;
+            match self.tick()? {
+                None | Some(StepResult::Output) => {}
+                Some(StepResult::WaitInput) => {
+                    // the input instruction did not run
+                    *budget += 1;
+                    return Ok(RunOutcome::WaitingInput);
+                }
+                Some(StepResult::Halt) => return Ok(RunOutcome::Halted),
+                Some(StepResult::Watch(hit)) => return Ok(RunOutcome::Watch(hit)),
+            }
+        }
+    }
+
+    /// Run until `n` more values are in the output queue.
+    pub fn run_until_outputs(&mut self, n: usize) -> ComputerResult<RunOutcome<W>> {
+        let target = self.output.len() + n;
+        self.run_until(|instant| instant.output.len() >= target)
+    }
+
+    /// Run until the machine waits for input, which is reported as
+    /// `RunOutcome::WaitingInput`.
+    pub fn run_until_input(&mut self) -> ComputerResult<RunOutcome<W>> {
+        self.run_until(|_| false)
+    }
+
+    /// Run until a new output completes `pattern` at the end of the output
+    /// queue, e.g. the prompt of a text adventure.
+    pub fn run_until_output_matches(&mut self, pattern: &[W]) -> ComputerResult<RunOutcome<W>> {
+        let start = self.output.len();
+        self.run_until(|instant| {
+            let out = &instant.output;
+            out.len() > start
+                && out.len() >= pattern.len()
+                && out.iter().skip(out.len() - pattern.len()).eq(pattern)
+        })
+    }
+}
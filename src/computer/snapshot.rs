@@ -0,0 +1,202 @@
+//! Versioned on-disk snapshots of an `Instant`
+//!
+//! A snapshot is a line-based text file:
+//!
+//! ```text
+//! intcode-snapshot 1
+//! pc 25
+//! base 1000
+//! memory dense 1077
+//! words 0 1102,34463338,34463338,63
+//! words 1000 7,0,0,2
+//! input 2
+//! output
+//! ```
+//!
+//! Memory is stored as runs of words starting at the given address, with
+//! every address not covered by a run being zero.
+
+use super::{Instant, Int, MemoryKind};
+use crate::*;
+use std::collections::VecDeque;
+use std::path::Path;
+
+const MAGIC: &str = "intcode-snapshot";
+const VERSION: u32 = 1;
+
+/// Zero gaps up to this length are stored inline instead of starting a new run.
+const MAX_GAP: usize = 8;
+
+/// Largest dense memory a snapshot may ask for, as it is allocated up front.
+const MAX_DENSE_LEN: usize = 1 << 24;
+
+fn join(vals: impl IntoIterator<Item = Int>) -> String {
+    vals.into_iter().map(|v| v.to_string()).join(",")
+}
+
+fn parse_list(input: &str) -> Result<Vec<Int>> {
+    input
+        .split(',')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse().with_context(|| format!("invalid word `{}`", v)))
+        .collect()
+}
+
+impl Instant {
+    pub fn write_snapshot<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{} {}", MAGIC, VERSION)?;
+        writeln!(writer, "pc {}", self.pc)?;
+        writeln!(writer, "base {}", self.base)?;
+        writeln!(
+            writer,
+            "memory {} {}",
+            self.mem.kind().name(),
+            self.mem.len()
+        )?;
+
+        let mut runs: Vec<(usize, Vec<Int>)> = Vec::new();
+        for (addr, val) in self.mem.cells() {
+            match runs.last_mut() {
+                Some((start, run)) if addr - (*start + run.len()) <= MAX_GAP => {
+                    run.resize(addr - *start, 0);
+                    run.push(val);
+                }
+                _ => runs.push((addr, vec![val])),
+            }
+        }
+        for (start, run) in runs {
+            writeln!(writer, "words {} {}", start, join(run))?;
+        }
+
+        writeln!(writer, "input {}", join(self.input.iter().copied()))?;
+        writeln!(writer, "output {}", join(self.output.iter().copied()))?;
+        Ok(())
+    }
+
+    pub fn read_snapshot<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines().enumerate();
+        let header = match lines.next() {
+            Some((_, line)) => line?,
+            None => bail!("empty snapshot"),
+        };
+        let version = header
+            .strip_prefix(MAGIC)
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .context("not an intcode snapshot")?;
+        if version != VERSION {
+            bail!("unsupported snapshot version {}", version);
+        }
+
+        let mut instant: Option<Instant> = None;
+        let mut pc = 0;
+        let mut base = 0;
+        let mut input = VecDeque::new();
+        let mut output = VecDeque::new();
+        for (idx, line) in lines {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.splitn(2, ' ');
+            let key = fields.next().unwrap();
+            let val = fields.next().unwrap_or("").trim();
+            let mut parse = || -> Result<()> {
+                match key {
+                    "pc" => pc = val.parse()?,
+                    "base" => base = val.parse()?,
+                    "memory" => {
+                        let mut fields = val.split_whitespace();
+                        let kind: MemoryKind = fields.next().context("missing kind")?.parse()?;
+                        let len: usize = fields.next().context("missing length")?.parse()?;
+                        if kind == MemoryKind::Dense && len > MAX_DENSE_LEN {
+                            bail!("dense memory of {} words is too large", len);
+                        }
+                        let mut mem = kind.create(&[]);
+                        if len > 0 {
+                            mem.write(len - 1, 0);
+                        }
+                        instant = Some(Instant::new(mem));
+                    }
+                    "words" => {
+                        let mem = &mut instant.as_mut().context("words before memory")?.mem;
+                        let mut fields = val.splitn(2, ' ');
+                        let start: usize = fields.next().unwrap().parse()?;
+                        let words = parse_list(fields.next().unwrap_or(""))?;
+                        match start.checked_add(words.len()) {
+                            Some(end) if end <= mem.len() => {}
+                            _ => bail!("words at {} beyond memory length {}", start, mem.len()),
+                        }
+                        for (off, word) in words.into_iter().enumerate() {
+                            mem.write(start + off, word);
+                        }
+                    }
+                    "input" => input = parse_list(val)?.into(),
+                    "output" => output = parse_list(val)?.into(),
+                    _ => bail!("unknown field `{}`", key),
+                }
+                Ok(())
+            };
+            parse().with_context(|| format!("line {}", idx + 1))?;
+        }
+
+        let mut instant = instant.context("missing memory")?;
+        instant.pc = pc;
+        instant.base = base;
+        instant.input = input;
+        instant.output = output;
+        Ok(instant)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("failed to create {:?}", path))?;
+        let mut writer = io::BufWriter::new(file);
+        self.write_snapshot(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open {:?}", path))?;
+        Self::read_snapshot(BufReader::new(file))
+            .with_context(|| format!("failed to load snapshot {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::Program;
+
+    fn read(snapshot: &str) -> Result<Instant> {
+        Instant::read_snapshot(snapshot.as_bytes())
+    }
+
+    #[test]
+    fn rejects_oversized_memory() {
+        let snapshot = "intcode-snapshot 1\nmemory dense 18446744073709551615\n";
+        assert!(read(snapshot).is_err());
+    }
+
+    #[test]
+    fn rejects_words_beyond_memory() {
+        let snapshot = "intcode-snapshot 1\nmemory dense 4\nwords 18446744073709551614 1,2\n";
+        assert!(read(snapshot).is_err());
+        let snapshot = "intcode-snapshot 1\nmemory sparse 4\nwords 3 1,2\n";
+        assert!(read(snapshot).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut instant = Program::new(vec![1102, 34463338, 34463338, 63, 99]).start();
+        instant.push_input(2);
+        instant.mem.write(1000, 7);
+        let mut buf = Vec::new();
+        instant.write_snapshot(&mut buf).unwrap();
+        let loaded = Instant::read_snapshot(&buf[..]).unwrap();
+        assert_eq!(loaded.mem.cells(), instant.mem.cells());
+        assert_eq!(loaded.input, instant.input);
+    }
+}
@@ -0,0 +1,172 @@
+//! Declarative graphs of `Instant`s wired output to input
+
+use super::{ComputerError, Instant, Int, Program, StepResult};
+use std::fmt;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum TopologyError {
+    /// Every machine still running waits for input nobody will send.
+    Deadlock {
+        stuck: Vec<usize>,
+    },
+    Machine {
+        node: usize,
+        error: ComputerError,
+    },
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TopologyError::Deadlock { stuck } => write!(
+                f,
+                "deadlock: machines {} are waiting for input",
+                stuck
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            TopologyError::Machine { node, error } => write!(f, "machine {}: {}", node, error),
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+struct Node {
+    instant: Instant,
+    targets: Vec<usize>,
+    halted: bool,
+}
+
+/// A set of machines where every output of a machine is appended to the input
+/// of each machine it is connected to. Several machines connected to the same
+/// target share its input queue in the order their outputs are produced.
+#[derive(Default)]
+pub struct Topology {
+    nodes: Vec<Node>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a machine, returning its id.
+    pub fn add(&mut self, instant: Instant) -> usize {
+        self.nodes.push(Node {
+            instant,
+            targets: Vec::new(),
+            halted: false,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Add `n` copies of `prog`, each seeded with the matching element of
+    /// `seeds` if any, e.g. amplifier phase settings.
+    pub fn add_copies(&mut self, prog: &Program, n: usize, seeds: &[Int]) -> Vec<usize> {
+        (0..n)
+            .map(|i| match seeds.get(i) {
+                Some(&seed) => self.add(prog.start_with_input(&[seed])),
+                None => self.add(prog.start()),
+            })
+            .collect()
+    }
+
+    /// Queue `vals` as input of `node` before anything it receives from other
+    /// machines.
+    pub fn seed(&mut self, node: usize, vals: &[Int]) -> &mut Self {
+        self.nodes[node].instant.push_inputs(vals);
+        self
+    }
+
+    pub fn connect(&mut self, from: usize, to: usize) -> &mut Self {
+        self.nodes[from].targets.push(to);
+        self
+    }
+
+    pub fn chain(&mut self, nodes: &[usize]) -> &mut Self {
+        for pair in nodes.windows(2) {
+            self.connect(pair[0], pair[1]);
+        }
+        self
+    }
+
+    /// Chain `nodes` and feed the output of the last back into the first.
+    pub fn ring(&mut self, nodes: &[usize]) -> &mut Self {
+        self.chain(nodes);
+        if let (Some(&first), Some(&last)) = (nodes.first(), nodes.last()) {
+            self.connect(last, first);
+        }
+        self
+    }
+
+    pub fn fan_out(&mut self, from: usize, to: &[usize]) -> &mut Self {
+        for &node in to {
+            self.connect(from, node);
+        }
+        self
+    }
+
+    pub fn fan_in(&mut self, from: &[usize], to: usize) -> &mut Self {
+        for &node in from {
+            self.connect(node, to);
+        }
+        self
+    }
+
+    /// Run the machines round-robin until all of them halt, returning every
+    /// value each of them output.
+    pub fn run(mut self) -> Result<Vec<Vec<Int>>, TopologyError> {
+        let mut outputs = vec![Vec::new(); self.nodes.len()];
+        loop {
+            let mut progress = false;
+            for (id, out) in outputs.iter_mut().enumerate() {
+                if self.nodes[id].halted {
+                    continue;
+                }
+                let had_input = !self.nodes[id].instant.input.is_empty();
+                loop {
+                    let node = &mut self.nodes[id];
+                    let result = node
+                        .instant
+                        .step()
+                        .map_err(|error| TopologyError::Machine { node: id, error })?;
+                    match result {
+                        StepResult::Output => {
+                            progress = true;
+                            let val = node.instant.pop_output().unwrap();
+                            out.push(val);
+                            for i in 0..self.nodes[id].targets.len() {
+                                let target = self.nodes[id].targets[i];
+                                self.nodes[target].instant.push_input(val);
+                            }
+                        }
+                        StepResult::Halt => {
+                            progress = true;
+                            node.halted = true;
+                            break;
+                        }
+                        StepResult::WaitInput => {
+                            progress |= had_input;
+                            break;
+                        }
+                        StepResult::Watch(_) => {}
+                    }
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        let stuck: Vec<usize> = (0..self.nodes.len())
+            .filter(|&id| !self.nodes[id].halted)
+            .collect();
+        if stuck.is_empty() {
+            Ok(outputs)
+        } else {
+            Err(TopologyError::Deadlock { stuck })
+        }
+    }
+}
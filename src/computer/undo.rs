@@ -0,0 +1,157 @@
+//! Undo log for stepping an `Instant` backwards
+
+use super::{Instant, Int, Word};
+use std::collections::VecDeque;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct MemoryWrite<W = Int> {
+    pub addr: usize,
+    pub old: W,
+    pub new: W,
+}
+
+/// Everything needed to revert a single executed instruction. Built-in
+/// instructions touch at most one of each, custom opcodes may touch several.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct UndoRecord<W = Int> {
+    pub pc: usize,
+    pub base: W,
+    /// Memory writes in the order they happened.
+    pub writes: Vec<MemoryWrite<W>>,
+    /// Values consumed from the front of the input queue, in order.
+    pub inputs: Vec<W>,
+    /// Values appended to the output queue, in order.
+    pub outputs: Vec<W>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct UndoLog<W = Int> {
+    records: VecDeque<UndoRecord<W>>,
+    capacity: usize,
+    pending: Option<UndoRecord<W>>,
+}
+
+impl<W: Word> UndoLog<W> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity.min(1 << 16)),
+            capacity,
+            pending: None,
+        }
+    }
+
+    pub(super) fn begin(&mut self, pc: usize, base: W) {
+        self.pending = Some(UndoRecord {
+            pc,
+            base,
+            writes: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        });
+    }
+
+    pub(super) fn record_write(&mut self, addr: usize, old: W, new: W) {
+        if let Some(record) = &mut self.pending {
+            record.writes.push(MemoryWrite { addr, old, new });
+        }
+    }
+
+    pub(super) fn record_input(&mut self, val: W) {
+        if let Some(record) = &mut self.pending {
+            record.inputs.push(val);
+        }
+    }
+
+    pub(super) fn record_output(&mut self, val: W) {
+        if let Some(record) = &mut self.pending {
+            record.outputs.push(val);
+        }
+    }
+
+    pub(super) fn commit(&mut self) {
+        if let Some(record) = self.pending.take() {
+            if self.records.len() == self.capacity {
+                self.records.pop_front();
+            }
+            self.records.push_back(record);
+        }
+    }
+
+    pub(super) fn abort(&mut self) {
+        self.pending = None;
+    }
+}
+
+impl<W: Word> Instant<W> {
+    /// Record the effects of the last `capacity` instructions so they can be
+    /// reverted with `step_back`.
+    pub fn enable_undo(&mut self, capacity: usize) {
+        if capacity == 0 {
+            self.undo = None;
+        } else {
+            self.undo = Some(UndoLog::new(capacity));
+        }
+        self.update_instrumented();
+    }
+
+    pub fn with_undo(mut self, capacity: usize) -> Self {
+        self.enable_undo(capacity);
+        self
+    }
+
+    /// Recorded instructions, oldest first.
+    pub fn undo_history(&self) -> impl Iterator<Item = &UndoRecord<W>> {
+        self.undo.iter().flat_map(|log| log.records.iter())
+    }
+
+    /// Revert the most recent recorded instruction.
+    ///
+    /// Outputs produced by that instruction are only removed while they are
+    /// still at the end of the output queue.
+    pub fn step_back(&mut self) -> Option<UndoRecord<W>> {
+        let record = self.undo.as_mut()?.records.pop_back()?;
+        for write in record.writes.iter().rev() {
+            self.mem.write(write.addr, write.old.clone());
+            self.stale_code.invalidate(write.addr);
+            if let Some(detector) = &mut self.loop_detector {
+                detector.record_write(write.addr, &write.new, &write.old);
+            }
+        }
+        if let Some(detector) = &mut self.loop_detector {
+            detector.reset();
+        }
+        for val in record.inputs.iter().rev() {
+            self.input.push_front(val.clone());
+        }
+        for val in record.outputs.iter().rev() {
+            if self.output.back() != Some(val) {
+                break;
+            }
+            self.output.pop_back();
+        }
+        self.pc = record.pc;
+        self.base = record.base.clone();
+        Some(record)
+    }
+
+    /// Step back until the instruction at `pc` is about to execute again.
+    /// Returns `false` if the undo history runs out first.
+    pub fn run_back_to(&mut self, pc: usize) -> bool {
+        while self.step_back().is_some() {
+            if self.pc == pc {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Find the most recent recorded instruction that wrote to `addr`.
+    pub fn find_last_write(&self, addr: usize) -> Option<&UndoRecord<W>> {
+        self.undo
+            .as_ref()?
+            .records
+            .iter()
+            .rev()
+            .find(|r| r.writes.iter().any(|w| w.addr == addr))
+    }
+}
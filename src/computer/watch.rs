@@ -0,0 +1,128 @@
+//! Memory watchpoints and detection of self-modifying code
+
+use super::{Instant, Int, Word};
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+    /// A write storing a value different from the one there before.
+    Change,
+    /// A write into a word which was part of an executed instruction.
+    CodeWrite,
+}
+
+/// Which instruction touched which address, reported by
+/// `StepResult::Watch` once that instruction has completed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct WatchHit<W = Int> {
+    pub pc: usize,
+    pub addr: usize,
+    pub access: Access,
+    /// Value of the word before the instruction.
+    pub old: W,
+    /// Value of the word after the instruction.
+    pub new: W,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct Watchpoints<W = Int> {
+    points: Vec<(RangeInclusive<usize>, Access)>,
+    executed: Option<HashSet<usize>>,
+    pub(super) hit: Option<WatchHit<W>>,
+}
+
+impl<W: Word> Watchpoints<W> {
+    fn report(&mut self, hit: WatchHit<W>) {
+        // an instruction touches at most a few words, the first hit wins
+        if self.hit.is_none() {
+            self.hit = Some(hit);
+        }
+    }
+
+    fn watched(&self, addr: usize, access: Access) -> bool {
+        self.points
+            .iter()
+            .any(|(range, a)| *a == access && range.contains(&addr))
+    }
+
+    pub(super) fn record_execute(&mut self, pc: usize, size: usize) {
+        if let Some(executed) = &mut self.executed {
+            executed.extend(pc..pc + size);
+        }
+    }
+
+    pub(super) fn record_read(&mut self, pc: usize, addr: usize, val: &W) {
+        if self.watched(addr, Access::Read) {
+            self.report(WatchHit {
+                pc,
+                addr,
+                access: Access::Read,
+                old: val.clone(),
+                new: val.clone(),
+            });
+        }
+    }
+
+    pub(super) fn record_write(&mut self, pc: usize, addr: usize, old: &W, new: &W) {
+        let code = match &self.executed {
+            Some(executed) => executed.contains(&addr),
+            None => false,
+        };
+        let access = if code {
+            Access::CodeWrite
+        } else if old != new && self.watched(addr, Access::Change) {
+            Access::Change
+        } else if self.watched(addr, Access::Write) {
+            Access::Write
+        } else {
+            return;
+        };
+        self.report(WatchHit {
+            pc,
+            addr,
+            access,
+            old: old.clone(),
+            new: new.clone(),
+        });
+    }
+}
+
+impl<W: Word> Instant<W> {
+    fn watchpoints(&mut self) -> &mut Watchpoints<W> {
+        self.instrumented = true;
+        self.watch.get_or_insert_with(Box::default)
+    }
+
+    /// Pause with `StepResult::Watch` after any instruction accessing an
+    /// address in `range` the given way.
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<usize>, access: Access) {
+        self.watchpoints().points.push((range, access));
+    }
+
+    pub fn with_watchpoint(mut self, range: RangeInclusive<usize>, access: Access) -> Self {
+        self.add_watchpoint(range, access);
+        self
+    }
+
+    /// Pause with an `Access::CodeWrite` hit after any write into a word of a
+    /// previously executed instruction, operands included.
+    pub fn watch_code_writes(&mut self) {
+        let watch = self.watchpoints();
+        if watch.executed.is_none() {
+            watch.executed = Some(HashSet::new());
+        }
+    }
+
+    pub fn with_code_write_watch(mut self) -> Self {
+        self.watch_code_writes();
+        self
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watch = None;
+        self.update_instrumented();
+    }
+}
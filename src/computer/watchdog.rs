@@ -0,0 +1,211 @@
+//! Instruction budgets and detection of programs stuck in a silent loop
+
+use super::{ComputerError, ComputerResult, Instant, Int, Memory, StepResult, WatchHit, Word};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Outcome of `Instant::step_with_budget`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum BudgetedStep<W = Int> {
+    Output,
+    WaitInput,
+    Halt,
+    /// The budget ran out before the machine stopped. Calling
+    /// `step_with_budget` again with a fresh budget resumes execution.
+    BudgetExhausted,
+    Watch(WatchHit<W>),
+}
+
+impl<W> From<StepResult<W>> for BudgetedStep<W> {
+    fn from(result: StepResult<W>) -> Self {
+        match result {
+            StepResult::Output => BudgetedStep::Output,
+            StepResult::WaitInput => BudgetedStep::WaitInput,
+            StepResult::Halt => BudgetedStep::Halt,
+            StepResult::Watch(hit) => BudgetedStep::Watch(hit),
+        }
+    }
+}
+
+fn mix<W: Word>(addr: usize, val: &W) -> u64 {
+    if val.is_zero() {
+        return 0;
+    }
+    let val = match val.to_int() {
+        Some(val) => val as u64,
+        None => {
+            let mut hasher = DefaultHasher::new();
+            val.hash(&mut hasher);
+            hasher.finish()
+        }
+    };
+    // splitmix64 finalizer
+    let mut x = (addr as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ val;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[derive(Debug, Clone)]
+struct Checkpoint<W> {
+    pc: usize,
+    base: W,
+    hash: u64,
+    /// Value at the checkpoint of every address written since.
+    original: HashMap<usize, W>,
+}
+
+/// Brent-style cycle detector over `(pc, base, memory)`.
+///
+/// A fingerprint of memory is kept up to date on every write, so a repeat is
+/// only verified word by word when the fingerprints match.
+#[derive(Debug, Clone)]
+pub(super) struct LoopDetector<W = Int> {
+    hash: u64,
+    steps: u64,
+    next_checkpoint: u64,
+    checkpoint: Option<Checkpoint<W>>,
+}
+
+impl<W: Word> LoopDetector<W> {
+    fn new(mem: &dyn Memory<W>) -> Self {
+        let hash = mem
+            .cells()
+            .into_iter()
+            .fold(0u64, |h, (addr, val)| h.wrapping_add(mix(addr, &val)));
+        Self {
+            hash,
+            steps: 0,
+            next_checkpoint: 1,
+            checkpoint: None,
+        }
+    }
+
+    pub(super) fn record_write(&mut self, addr: usize, old: &W, new: &W) {
+        self.hash = self
+            .hash
+            .wrapping_sub(mix(addr, old))
+            .wrapping_add(mix(addr, new));
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint
+                .original
+                .entry(addr)
+                .or_insert_with(|| old.clone());
+        }
+    }
+
+    /// Forget everything seen so far, e.g. after the machine did some I/O.
+    pub(super) fn reset(&mut self) {
+        self.steps = 0;
+        self.next_checkpoint = 1;
+        self.checkpoint = None;
+    }
+
+    /// Account for one executed instruction, returning `true` if the machine
+    /// is back in a state it was in earlier.
+    pub(super) fn tick(&mut self, pc: usize, base: &W, mem: &dyn Memory<W>) -> bool {
+        self.steps += 1;
+        if let Some(checkpoint) = &self.checkpoint {
+            if checkpoint.pc == pc
+                && checkpoint.base == *base
+                && checkpoint.hash == self.hash
+                && checkpoint
+                    .original
+                    .iter()
+                    .all(|(&addr, val)| mem.read(addr) == *val)
+            {
+                return true;
+            }
+        }
+        if self.steps == self.next_checkpoint {
+            self.next_checkpoint *= 2;
+            self.checkpoint = Some(Checkpoint {
+                pc,
+                base: base.clone(),
+                hash: self.hash,
+                original: HashMap::new(),
+            });
+        }
+        false
+    }
+}
+
+impl<W: Word> Instant<W> {
+    /// Like `step`, but execute at most `*budget` instructions, deducting the
+    /// ones executed from `budget`.
+    pub fn step_with_budget(&mut self, budget: &mut usize) -> ComputerResult<BudgetedStep<W>> {
+        while *budget > 0 {
+            *budget -= 1;
+            if let Some(result) = self.tick()? {
+                if let StepResult::WaitInput = result {
+                    // the input instruction did not run
+                    *budget += 1;
+                }
+                return Ok(result.into());
+            }
+        }
+        Ok(BudgetedStep::BudgetExhausted)
+    }
+
+    /// Fail with `ComputerError::InfiniteLoop` as soon as the machine repeats
+    /// an earlier state without doing any I/O in between.
+    ///
+    /// Writes made directly through `mem` after this call are not tracked.
+    pub fn enable_loop_detection(&mut self) {
+        if self.loop_detector.is_none() {
+            self.loop_detector = Some(LoopDetector::new(self.mem.as_ref()));
+        }
+        self.update_instrumented();
+    }
+
+    pub fn with_loop_detection(mut self) -> Self {
+        self.enable_loop_detection();
+        self
+    }
+
+    pub(super) fn check_loop(&mut self) -> ComputerResult<()> {
+        if let Some(detector) = &mut self.loop_detector {
+            if detector.tick(self.pc, &self.base, self.mem.as_ref()) {
+                return Err(ComputerError::InfiniteLoop {
+                    pc: self.pc,
+                    word: self.read(self.pc).saturate(),
+                    base: self.base.saturate(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::Program;
+
+    #[test]
+    fn extension_io_resets_loop_detection() {
+        // opcode 42 echoes one input, then the program jumps back to it
+        let mut instant = Program::new(vec![42, 1105, 1, 0])
+            .start_with_input(&[1, 2, 3, 4, 5, 6, 7, 8])
+            .with_loop_detection();
+        instant.register_opcode(42, "ECHO", 0, |instant, _| {
+            match instant.input.pop_front() {
+                Some(val) => {
+                    instant.output.push_back(val);
+                    Ok(None)
+                }
+                None => Ok(Some(StepResult::WaitInput)),
+            }
+        });
+        for _ in 0..16 {
+            if let StepResult::WaitInput = instant.step().unwrap() {
+                break;
+            }
+        }
+        assert_eq!(
+            instant.output.iter().copied().collect::<Vec<_>>(),
+            (1..=8).collect::<Vec<Int>>()
+        );
+    }
+}
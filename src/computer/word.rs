@@ -0,0 +1,141 @@
+//! Word types an `Instant` can compute with
+
+use super::Int;
+use num::bigint::BigInt;
+use num::ToPrimitive;
+use std::fmt;
+use std::hash::Hash;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// A memory word. Opcodes and addresses are always `Int`s, so words only
+/// need to convert to one when used as such; `ComputerError` reports words
+/// saturated to `Int`.
+pub trait Word:
+    Clone + Default + fmt::Debug + fmt::Display + Eq + Ord + Hash + Send + Sync + 'static
+{
+    /// Whether overflows fail regardless of `ExecutionPolicy::overflow`.
+    const CHECKED: bool = false;
+
+    fn from_int(val: Int) -> Self;
+
+    /// The value as an `Int`, or `None` if it does not fit.
+    fn to_int(&self) -> Option<Int>;
+
+    /// The sum, and whether it overflowed.
+    fn overflowing_add(&self, rhs: &Self) -> (Self, bool);
+
+    /// The product, and whether it overflowed.
+    fn overflowing_mul(&self, rhs: &Self) -> (Self, bool);
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn saturate(&self) -> Int {
+        match self.to_int() {
+            Some(val) => val,
+            None if *self < Self::default() => Int::MIN,
+            None => Int::MAX,
+        }
+    }
+}
+
+impl Word for i64 {
+    fn from_int(val: Int) -> Self {
+        val
+    }
+
+    fn to_int(&self) -> Option<Int> {
+        Some(*self)
+    }
+
+    fn overflowing_add(&self, rhs: &Self) -> (Self, bool) {
+        i64::overflowing_add(*self, *rhs)
+    }
+
+    fn overflowing_mul(&self, rhs: &Self) -> (Self, bool) {
+        i64::overflowing_mul(*self, *rhs)
+    }
+}
+
+impl Word for i128 {
+    fn from_int(val: Int) -> Self {
+        val.into()
+    }
+
+    fn to_int(&self) -> Option<Int> {
+        if *self >= Int::MIN.into() && *self <= Int::MAX.into() {
+            Some(*self as Int)
+        } else {
+            None
+        }
+    }
+
+    fn overflowing_add(&self, rhs: &Self) -> (Self, bool) {
+        i128::overflowing_add(*self, *rhs)
+    }
+
+    fn overflowing_mul(&self, rhs: &Self) -> (Self, bool) {
+        i128::overflowing_mul(*self, *rhs)
+    }
+}
+
+/// An `Int` whose overflows always fail with `ComputerError::Overflow`.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct CheckedInt(pub Int);
+
+impl fmt::Display for CheckedInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for CheckedInt {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        s.parse().map(CheckedInt)
+    }
+}
+
+impl Word for CheckedInt {
+    const CHECKED: bool = true;
+
+    fn from_int(val: Int) -> Self {
+        CheckedInt(val)
+    }
+
+    fn to_int(&self) -> Option<Int> {
+        Some(self.0)
+    }
+
+    fn overflowing_add(&self, rhs: &Self) -> (Self, bool) {
+        let (val, overflow) = self.0.overflowing_add(rhs.0);
+        (CheckedInt(val), overflow)
+    }
+
+    fn overflowing_mul(&self, rhs: &Self) -> (Self, bool) {
+        let (val, overflow) = self.0.overflowing_mul(rhs.0);
+        (CheckedInt(val), overflow)
+    }
+}
+
+/// Arbitrary precision, never overflows.
+impl Word for BigInt {
+    fn from_int(val: Int) -> Self {
+        val.into()
+    }
+
+    fn to_int(&self) -> Option<Int> {
+        self.to_i64()
+    }
+
+    fn overflowing_add(&self, rhs: &Self) -> (Self, bool) {
+        (self + rhs, false)
+    }
+
+    fn overflowing_mul(&self, rhs: &Self) -> (Self, bool) {
+        (self * rhs, false)
+    }
+}